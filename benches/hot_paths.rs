@@ -0,0 +1,163 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gpn_mazing_bot::{
+    board_tracker::BoardTracker, distance::calculate_distances, reachability,
+    shortest_path::shortest_path_next_direction,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+const BOARD_SIZE: (usize, usize) = (40, 40);
+const PLAYER_COUNT: usize = 8;
+const OCCUPANCIES: [f64; 3] = [0.1, 0.4, 0.7];
+
+fn random_free_cell(board: &BoardTracker, rng: &mut StdRng) -> (usize, usize) {
+    let free_cells: Vec<_> = board.free_cells().collect();
+    *free_cells.choose(rng).unwrap()
+}
+
+// Players walk randomly without crashing until the given fraction of the board
+// is taken. Players that get stuck jump to a random free cell instead of
+// dying, so that the board ends up with PLAYER_COUNT living heads.
+fn board_with_occupancy(occupancy: f64, seed: u64) -> BoardTracker {
+    let (width, height) = BOARD_SIZE;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut board = BoardTracker::new(width, height);
+    for player_id in 0..PLAYER_COUNT {
+        let pos = random_free_cell(&board, &mut rng);
+        board.record_pos(player_id, pos);
+    }
+
+    let target_count = (occupancy * (width * height) as f64) as usize;
+    let mut occupied_count = PLAYER_COUNT;
+    while occupied_count < target_count {
+        for player_id in 0..PLAYER_COUNT {
+            let pos = board.get_player_latest_pos(player_id).unwrap();
+            let new_pos = match board.safe_moves(player_id).choose(&mut rng) {
+                Some(&direction) => board.neighbor_pos(pos, direction).unwrap(),
+                None => random_free_cell(&board, &mut rng),
+            };
+            board.record_pos(player_id, new_pos);
+            occupied_count += 1;
+        }
+    }
+    board
+}
+
+// The living player with the most room, so that searches from its head
+// don't end immediately
+fn living_player(board: &BoardTracker) -> usize {
+    (0..PLAYER_COUNT)
+        .max_by_key(|&player_id| {
+            let head = board.get_player_latest_pos(player_id).unwrap();
+            board
+                .safe_moves(player_id)
+                .into_iter()
+                .map(|direction| {
+                    reachability::calculate_reachable_with_count(
+                        BOARD_SIZE,
+                        board.topology(),
+                        board,
+                        board.neighbor_pos(head, direction).unwrap(),
+                    )
+                    .1
+                })
+                .max()
+                .unwrap_or(0)
+        })
+        .unwrap()
+}
+
+fn boards() -> Vec<(f64, BoardTracker)> {
+    OCCUPANCIES
+        .iter()
+        .map(|&occupancy| (occupancy, board_with_occupancy(occupancy, 1)))
+        .collect()
+}
+
+fn bench_distances(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_distances");
+    for (occupancy, board) in boards() {
+        let occupied_mask = board.occupied_mask();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(occupancy),
+            &occupied_mask,
+            |b, occupied_mask| {
+                b.iter(|| {
+                    calculate_distances(BOARD_SIZE, board.topology(), black_box(occupied_mask))
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_reachable(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_reachable");
+    for (occupancy, board) in boards() {
+        let occupied_mask = board.occupied_mask();
+        let start = board.get_player_latest_pos(living_player(&board)).unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(occupancy),
+            &occupied_mask,
+            |b, occupied_mask| {
+                b.iter(|| {
+                    reachability::calculate_reachable(
+                        BOARD_SIZE,
+                        board.topology(),
+                        black_box(occupied_mask),
+                        start,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_shortest_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("shortest_path_next_direction");
+    for (occupancy, board) in boards() {
+        let occupied_mask = board.occupied_mask();
+        let start = board.get_player_latest_pos(living_player(&board)).unwrap();
+        // The free cell furthest along in row-major order, so the search has
+        // to cover most of the board
+        let target = board.free_cells().last().unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(occupancy),
+            &occupied_mask,
+            |b, occupied_mask| {
+                b.iter(|| {
+                    shortest_path_next_direction(
+                        BOARD_SIZE,
+                        board.topology(),
+                        black_box(occupied_mask),
+                        start,
+                        target,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_playout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("playout");
+    for (occupancy, mut board) in boards() {
+        let own_player_id = living_player(&board);
+        group.bench_function(BenchmarkId::from_parameter(occupancy), |b| {
+            b.iter(|| gpn_mazing_bot::bench_playout(&mut board, own_player_id, 120, 1))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_distances,
+    bench_reachable,
+    bench_shortest_path,
+    bench_playout
+);
+criterion_main!(benches);
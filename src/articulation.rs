@@ -0,0 +1,96 @@
+use crate::direction::{Direction, Topology};
+
+pub fn articulation_points(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &[bool],
+    start: (usize, usize),
+) -> Vec<bool> {
+    let (width, height) = size;
+
+    assert_eq!(occupied_mask.len(), width * height);
+
+    let mut is_articulation = vec![false; width * height];
+    let mut discovered = vec![usize::MAX; width * height];
+    let mut low = vec![usize::MAX; width * height];
+
+    let start_i = start.1 * width + start.0;
+    let passable = |i: usize| !occupied_mask[i] || i == start_i;
+
+    struct Frame {
+        node: usize,
+        parent: Option<usize>,
+        next_direction: usize,
+        skipped_parent: bool,
+    }
+
+    // Iterative DFS so large boards don't overflow the stack
+    let mut next_time = 0;
+    let mut root_children = 0;
+    discovered[start_i] = next_time;
+    low[start_i] = next_time;
+    next_time += 1;
+    let mut stack = vec![Frame {
+        node: start_i,
+        parent: None,
+        next_direction: 0,
+        skipped_parent: false,
+    }];
+
+    while let Some(frame) = stack.last_mut() {
+        let node = frame.node;
+        if frame.next_direction < 4 {
+            let direction = Direction::all_directions()[frame.next_direction];
+            frame.next_direction += 1;
+
+            let pos = (node % width, node / width);
+            let new_pos = match topology.offset_pos(pos, direction, size) {
+                Some(new_pos) => new_pos,
+                None => continue,
+            };
+            let new_i = new_pos.1 * width + new_pos.0;
+
+            if new_i == node || !passable(new_i) {
+                continue;
+            }
+            // On narrow wrapping boards two directions can lead to the same
+            // neighbor, so only the first edge back to the parent is ignored
+            if Some(new_i) == frame.parent && !frame.skipped_parent {
+                frame.skipped_parent = true;
+                continue;
+            }
+
+            if discovered[new_i] == usize::MAX {
+                discovered[new_i] = next_time;
+                low[new_i] = next_time;
+                next_time += 1;
+                if node == start_i {
+                    root_children += 1;
+                }
+                stack.push(Frame {
+                    node: new_i,
+                    parent: Some(node),
+                    next_direction: 0,
+                    skipped_parent: false,
+                });
+            } else {
+                low[node] = low[node].min(discovered[new_i]);
+            }
+        } else {
+            let parent = frame.parent;
+            stack.pop();
+            if let Some(parent) = parent {
+                low[parent] = low[parent].min(low[node]);
+                if parent != start_i && low[node] >= discovered[parent] {
+                    is_articulation[parent] = true;
+                }
+            }
+        }
+    }
+
+    if root_children > 1 {
+        is_articulation[start_i] = true;
+    }
+
+    is_articulation
+}
@@ -0,0 +1,6 @@
+fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    gpn_mazing_bot::run_simulation(&args)
+}
@@ -1,45 +1,242 @@
-use crate::direction::Direction;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
 
-#[derive(Clone)]
+use anyhow::{anyhow, Result};
+
+use crate::direction::{Direction, Topology};
+
+pub trait CellMask {
+    fn len(&self) -> usize;
+    fn get(&self, i: usize) -> bool;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CellMask for [bool] {
+    fn len(&self) -> usize {
+        <[bool]>::len(self)
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self[i]
+    }
+}
+
+impl CellMask for Vec<bool> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self[i]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitMask {
+    len: usize,
+    words: Vec<u64>,
+}
+
+impl BitMask {
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    pub fn set(&mut self, i: usize, value: bool) {
+        assert!(i < self.len);
+        if value {
+            self.words[i / 64] |= 1 << (i % 64);
+        } else {
+            self.words[i / 64] &= !(1 << (i % 64));
+        }
+    }
+}
+
+impl CellMask for BitMask {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, i: usize) -> bool {
+        assert!(i < self.len);
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+}
+
+// Lets flood fills read the board directly without building a mask first
+impl CellMask for BoardTracker {
+    fn len(&self) -> usize {
+        self.board.len()
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.occupied[i]
+    }
+}
+
+impl From<&[bool]> for BitMask {
+    fn from(mask: &[bool]) -> Self {
+        let mut bit_mask = BitMask::new(mask.len());
+        for (i, &value) in mask.iter().enumerate() {
+            bit_mask.set(i, value);
+        }
+        bit_mask
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 struct BoardTrackerPlayer {
     latest_pos: Option<(usize, usize)>,
+    previous_pos: Option<(usize, usize)>,
     dead: bool,
+    trail: Vec<(usize, usize)>,
+}
+
+#[derive(Clone)]
+enum BoardChange {
+    Cell {
+        i: usize,
+        cell: CellId,
+    },
+    Player {
+        player_id: usize,
+        latest_pos: Option<(usize, usize)>,
+        previous_pos: Option<(usize, usize)>,
+        dead: bool,
+    },
+    TrailPush(usize),
+    PlayerCount(usize),
 }
 
+// Games have far fewer than 65k players, and the smaller cells keep flood
+// fills over the board in cache
+type CellId = u16;
+
 #[derive(Clone)]
 pub struct BoardTracker {
     width: usize,
     height: usize,
-    board: Vec<usize>,
+    topology: Topology,
+    board: Vec<CellId>,
+    // Whether each board cell is taken, updated along with board so that
+    // occupied_mask doesn't have to rebuild it
+    occupied: Vec<bool>,
     players: Vec<BoardTrackerPlayer>,
+    player_names: HashMap<usize, String>,
+    change_log: Option<Vec<BoardChange>>,
+}
+
+// The change log is bookkeeping for rollback, not part of the board state
+impl PartialEq for BoardTracker {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.topology == other.topology
+            && self.board == other.board
+            && self.players == other.players
+            && self.player_names == other.player_names
+    }
+}
+
+impl Eq for BoardTracker {}
+
+impl std::fmt::Debug for BoardTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "BoardTracker {}x{}", self.width, self.height)?;
+        write!(f, "{}", self.render_ascii_with_heads())
+    }
 }
 
 impl BoardTracker {
-    const NO_PLAYER: usize = usize::MAX;
+    const NO_PLAYER: CellId = CellId::MAX;
+    pub const MAX_PLAYER_ID: usize = Self::NO_PLAYER as usize - 1;
+    const BYTES_MAGIC: &'static [u8] = b"GPNB";
+
+    fn cell_id(player_id: usize) -> Option<CellId> {
+        if player_id <= Self::MAX_PLAYER_ID {
+            Some(player_id as CellId)
+        } else {
+            None
+        }
+    }
+
+    fn cell_player(cell: CellId) -> Option<usize> {
+        match cell {
+            Self::NO_PLAYER => None,
+            cell => Some(cell as usize),
+        }
+    }
 
     pub fn new(width: usize, height: usize) -> Self {
+        assert!(width > 0 && height > 0, "board must not be empty");
         Self {
             width,
             height,
+            topology: Topology::Torus,
             board: vec![Self::NO_PLAYER; width * height],
+            occupied: vec![false; width * height],
             players: Vec::new(),
+            player_names: HashMap::new(),
+            change_log: None,
         }
     }
 
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
     pub fn board_size(&self) -> (usize, usize) {
         (self.width, self.height)
     }
 
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    fn log_change(&mut self, change: BoardChange) {
+        if let Some(change_log) = &mut self.change_log {
+            change_log.push(change);
+        }
+    }
+
+    fn set_cell(&mut self, i: usize, cell: CellId) {
+        self.log_change(BoardChange::Cell {
+            i,
+            cell: self.board[i],
+        });
+        self.board[i] = cell;
+        self.occupied[i] = cell != Self::NO_PLAYER;
+    }
+
     fn get_or_create_internal_player_mut(&mut self, player_id: usize) -> &mut BoardTrackerPlayer {
         if player_id >= self.players.len() {
+            self.log_change(BoardChange::PlayerCount(self.players.len()));
             self.players.resize(
                 player_id + 1,
                 BoardTrackerPlayer {
                     latest_pos: None,
+                    previous_pos: None,
                     dead: false,
+                    trail: Vec::new(),
                 },
             );
         }
+        let player = &self.players[player_id];
+        self.log_change(BoardChange::Player {
+            player_id,
+            latest_pos: player.latest_pos,
+            previous_pos: player.previous_pos,
+            dead: player.dead,
+        });
         &mut self.players[player_id]
     }
 
@@ -63,53 +260,445 @@ impl BoardTracker {
         self.players.len()
     }
 
+    // Players that have had a pos, unlike count_seen which also counts ids
+    // that only appeared in a die message
+    pub fn count_positioned(&self) -> usize {
+        self.players
+            .iter()
+            .filter(|p| p.latest_pos.is_some())
+            .count()
+    }
+
+    pub fn count_opponents_dead(&self, own_player: usize) -> usize {
+        self.players
+            .iter()
+            .enumerate()
+            .filter(|&(player_id, p)| player_id != own_player && p.dead)
+            .count()
+    }
+
+    pub fn count_opponents_alive(&self, own_player: usize) -> usize {
+        self.players
+            .iter()
+            .enumerate()
+            .filter(|&(player_id, p)| player_id != own_player && !p.dead)
+            .count()
+    }
+
+    // Names are stored separately from players, so that a name arriving
+    // before the first pos doesn't count as a seen player
+    pub fn set_player_name(&mut self, player_id: usize, name: String) {
+        self.player_names.insert(player_id, name);
+    }
+
+    pub fn player_name(&self, player_id: usize) -> Option<&str> {
+        self.player_names.get(&player_id).map(|name| name.as_str())
+    }
+
     pub fn get_cell_player(&self, (x, y): (usize, usize)) -> Option<usize> {
-        match self.board[y * self.width + x] {
-            Self::NO_PLAYER => None,
-            player_id => Some(player_id),
+        Self::cell_player(self.board[y * self.width + x])
+    }
+
+    // Outer None if the position is off the board. get_cell_player doesn't
+    // check, since positions from offset_pos are always in range.
+    pub fn try_get_cell_player(&self, (x, y): (usize, usize)) -> Option<Option<usize>> {
+        if x >= self.width || y >= self.height {
+            return None;
         }
+        Some(self.get_cell_player((x, y)))
     }
 
     pub fn get_player_latest_pos(&self, player_id: usize) -> Option<(usize, usize)> {
         self.players.get(player_id).and_then(|p| p.latest_pos)
     }
 
+    pub fn get_player_previous_pos(&self, player_id: usize) -> Option<(usize, usize)> {
+        self.players.get(player_id).and_then(|p| p.previous_pos)
+    }
+
+    pub fn get_player_heading(&self, player_id: usize) -> Option<Direction> {
+        let previous_pos = self.get_player_previous_pos(player_id)?;
+        let latest_pos = self.get_player_latest_pos(player_id)?;
+        Direction::all_directions()
+            .into_iter()
+            .find(|&direction| self.neighbor_pos(previous_pos, direction) == Some(latest_pos))
+    }
+
+    pub fn is_reverse_move(&self, player_id: usize, direction: Direction) -> bool {
+        self.get_player_heading(player_id)
+            .is_some_and(|heading| heading.reverse() == direction)
+    }
+
+    // Moves that don't run into an occupied cell and aren't a reversal
+    pub fn safe_moves(&self, player_id: usize) -> Vec<Direction> {
+        let player_pos = match self.get_player_latest_pos(player_id) {
+            Some(player_pos) => player_pos,
+            None => return Vec::new(),
+        };
+        Direction::all_directions()
+            .into_iter()
+            .filter(|&direction| {
+                self.neighbor_pos(player_pos, direction)
+                    .is_some_and(|new_pos| self.get_cell_player(new_pos).is_none())
+                    && !self.is_reverse_move(player_id, direction)
+            })
+            .collect()
+    }
+
+    // Like safe_moves, but also avoids cells that a living opponent could move
+    // into this turn
+    pub fn safe_moves_conservative(&self, player_id: usize) -> Vec<Direction> {
+        let player_pos = match self.get_player_latest_pos(player_id) {
+            Some(player_pos) => player_pos,
+            None => return Vec::new(),
+        };
+        let mask = self.conservative_occupied_mask_living(player_id);
+        self.safe_moves(player_id)
+            .into_iter()
+            .filter(|&direction| {
+                let (x, y) = self.neighbor_pos(player_pos, direction).unwrap();
+                !mask[y * self.width + x]
+            })
+            .collect()
+    }
+
+    // Hash of the occupied cells and where each player is, which is all that
+    // playouts from this position depend on. Only comparable within one run.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.width.hash(&mut hasher);
+        self.height.hash(&mut hasher);
+        self.occupied.hash(&mut hasher);
+        for player in &self.players {
+            player.latest_pos.hash(&mut hasher);
+            player.previous_pos.hash(&mut hasher);
+            player.dead.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    pub fn player_trail(&self, player_id: usize) -> &[(usize, usize)] {
+        self.players
+            .get(player_id)
+            .map_or(&[], |p| p.trail.as_slice())
+    }
+
     pub fn record_pos(&mut self, player_id: usize, (x, y): (usize, usize)) -> bool {
-        let v = &mut self.board[y * self.width + x];
-        let duplicate = *v != Self::NO_PLAYER;
-        *v = player_id;
+        let i = y * self.width + x;
+        let cell = Self::cell_id(player_id)
+            .unwrap_or_else(|| panic!("player id {} is too large", player_id));
+        let duplicate = self.board[i] != Self::NO_PLAYER;
+        self.set_cell(i, cell);
 
+        let max_trail_len = self.board.len();
         let player = self.get_or_create_internal_player_mut(player_id);
+        player.previous_pos = player.latest_pos;
         player.latest_pos = Some((x, y));
         player.dead = false;
+        // Cells can't repeat within a round, so this only triggers on bad input
+        if player.trail.len() < max_trail_len {
+            player.trail.push((x, y));
+            self.log_change(BoardChange::TrailPush(player_id));
+        }
 
         duplicate
     }
 
+    // With clear, the player's trail is removed from the board, which is what
+    // the server does and what the live board does by default. Playouts pass
+    // false for some rollouts to stay pessimistic about freed space.
+    //
+    // Repeated deaths are ignored, except that a later death can still clear
+    // the trail. A death for a player we haven't seen a pos for creates a dead
+    // entry, which count_positioned doesn't count.
     pub fn record_death(&mut self, player_id: usize, clear: bool) {
-        let player = self.get_or_create_internal_player_mut(player_id);
-        player.dead = true;
+        let already_dead = self.players.get(player_id).is_some_and(|p| p.dead);
+        if !already_dead {
+            self.get_or_create_internal_player_mut(player_id).dead = true;
+        }
 
-        if clear {
+        // Ids too large for a cell can't have a trail
+        if let Some(cell) = Self::cell_id(player_id).filter(|_| clear) {
             for i in 0..self.board.len() {
-                if self.board[i] == player_id {
-                    self.board[i] = Self::NO_PLAYER;
+                if self.board[i] == cell {
+                    self.set_cell(i, Self::NO_PLAYER);
+                }
+            }
+        }
+    }
+
+    // Row by row, in the same order as cell indices
+    pub fn all_cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (width, height) = (self.width, self.height);
+        (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+    }
+
+    pub fn free_cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.all_cells()
+            .filter(|&pos| self.get_cell_player(pos).is_none())
+    }
+
+    // Little-endian u32s throughout. Player names are sorted by id so that
+    // equal boards give equal bytes. The change log isn't included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn push_u32(bytes: &mut Vec<u8>, value: usize) {
+            bytes.extend_from_slice(&u32::try_from(value).unwrap().to_le_bytes());
+        }
+        fn push_pos(bytes: &mut Vec<u8>, pos: Option<(usize, usize)>) {
+            match pos {
+                Some((x, y)) => {
+                    bytes.push(1);
+                    push_u32(bytes, x);
+                    push_u32(bytes, y);
+                }
+                None => bytes.push(0),
+            }
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(Self::BYTES_MAGIC);
+        push_u32(&mut bytes, self.width);
+        push_u32(&mut bytes, self.height);
+        for &cell in &self.board {
+            let player_id = Self::cell_player(cell).map_or(u32::MAX, |player_id| player_id as u32);
+            bytes.extend_from_slice(&player_id.to_le_bytes());
+        }
+
+        push_u32(&mut bytes, self.players.len());
+        for player in &self.players {
+            push_pos(&mut bytes, player.latest_pos);
+            push_pos(&mut bytes, player.previous_pos);
+            bytes.push(player.dead as u8);
+            push_u32(&mut bytes, player.trail.len());
+            for &pos in &player.trail {
+                push_u32(&mut bytes, pos.0);
+                push_u32(&mut bytes, pos.1);
+            }
+        }
+
+        let mut names: Vec<_> = self.player_names.iter().collect();
+        names.sort();
+        push_u32(&mut bytes, names.len());
+        for (&player_id, name) in names {
+            push_u32(&mut bytes, player_id);
+            push_u32(&mut bytes, name.len());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<BoardTracker> {
+        let mut reader = ByteReader { bytes, offset: 0 };
+        if reader.take(Self::BYTES_MAGIC.len())? != Self::BYTES_MAGIC {
+            return Err(anyhow!("not a board snapshot"));
+        }
+
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        if width == 0 || height == 0 {
+            return Err(anyhow!("invalid board size {}x{}", width, height));
+        }
+        let mut board_tracker = BoardTracker::new(width, height);
+        let read_pos = |reader: &mut ByteReader| -> Result<(usize, usize)> {
+            let (x, y) = (reader.u32()?, reader.u32()?);
+            if x >= width || y >= height {
+                return Err(anyhow!("position out of bounds: ({}, {})", x, y));
+            }
+            Ok((x, y))
+        };
+        let read_optional_pos = |reader: &mut ByteReader| -> Result<Option<(usize, usize)>> {
+            match reader.u8()? {
+                0 => Ok(None),
+                1 => Ok(Some(read_pos(reader)?)),
+                flag => Err(anyhow!("invalid position flag: {}", flag)),
+            }
+        };
+
+        for cell in board_tracker.board.iter_mut() {
+            let player_id = reader.u32()?;
+            *cell = if player_id == u32::MAX as usize {
+                Self::NO_PLAYER
+            } else {
+                Self::cell_id(player_id)
+                    .ok_or_else(|| anyhow!("player id too large: {}", player_id))?
+            };
+        }
+        board_tracker.occupied = board_tracker
+            .board
+            .iter()
+            .map(|&player_id| player_id != Self::NO_PLAYER)
+            .collect();
+
+        let player_count = reader.u32()?;
+        for _ in 0..player_count {
+            let latest_pos = read_optional_pos(&mut reader)?;
+            let previous_pos = read_optional_pos(&mut reader)?;
+            let dead = match reader.u8()? {
+                0 => false,
+                1 => true,
+                flag => return Err(anyhow!("invalid dead flag: {}", flag)),
+            };
+            let trail_len = reader.u32()?;
+            if trail_len > width * height {
+                return Err(anyhow!("trail longer than board: {}", trail_len));
+            }
+            let trail = (0..trail_len)
+                .map(|_| read_pos(&mut reader))
+                .collect::<Result<_>>()?;
+            board_tracker.players.push(BoardTrackerPlayer {
+                latest_pos,
+                previous_pos,
+                dead,
+                trail,
+            });
+        }
+        if let Some(player_id) = board_tracker
+            .board
+            .iter()
+            .filter_map(|&cell| Self::cell_player(cell))
+            .find(|&player_id| player_id >= player_count)
+        {
+            return Err(anyhow!("cell owned by unknown player {}", player_id));
+        }
+
+        let name_count = reader.u32()?;
+        for _ in 0..name_count {
+            let player_id = reader.u32()?;
+            let name_len = reader.u32()?;
+            let name = std::str::from_utf8(reader.take(name_len)?)?;
+            board_tracker.set_player_name(player_id, name.to_string());
+        }
+
+        if reader.offset != bytes.len() {
+            return Err(anyhow!("trailing bytes after board snapshot"));
+        }
+        Ok(board_tracker)
+    }
+
+    // Like render_ascii, but the heads of living players are drawn as "@"
+    pub fn render_ascii_with_heads(&self) -> String {
+        let heads = self.head_positions();
+        let mut output = String::with_capacity((self.width + 1) * self.height);
+        for (x, y) in self.all_cells() {
+            output.push(match self.get_cell_player((x, y)) {
+                Some(_) if heads.contains(&(x, y)) => '@',
+                Some(player_id) => char::from_digit((player_id % 36) as u32, 36).unwrap(),
+                None => '.',
+            });
+            if x + 1 == self.width {
+                output.push('\n');
+            }
+        }
+        output
+    }
+
+    pub fn render_ascii(&self) -> String {
+        let mut output = String::with_capacity((self.width + 1) * self.height);
+        for (x, y) in self.all_cells() {
+            output.push(match self.get_cell_player((x, y)) {
+                Some(player_id) => char::from_digit((player_id % 36) as u32, 36).unwrap(),
+                None => '.',
+            });
+            if x + 1 == self.width {
+                output.push('\n');
+            }
+        }
+        output
+    }
+
+    // Starts recording changes so that they can be undone with rollback
+    pub fn checkpoint(&mut self) {
+        assert!(self.change_log.is_none(), "checkpoint already active");
+        self.change_log = Some(Vec::new());
+    }
+
+    pub fn rollback(&mut self) {
+        let change_log = self.change_log.take().expect("no active checkpoint");
+        for change in change_log.into_iter().rev() {
+            match change {
+                BoardChange::Cell { i, cell } => {
+                    self.board[i] = cell;
+                    self.occupied[i] = cell != Self::NO_PLAYER;
                 }
+                BoardChange::Player {
+                    player_id,
+                    latest_pos,
+                    previous_pos,
+                    dead,
+                } => {
+                    let player = &mut self.players[player_id];
+                    player.latest_pos = latest_pos;
+                    player.previous_pos = previous_pos;
+                    player.dead = dead;
+                }
+                BoardChange::TrailPush(player_id) => {
+                    self.players[player_id].trail.pop();
+                }
+                BoardChange::PlayerCount(count) => self.players.truncate(count),
             }
         }
     }
 
+    // Always wraps around, use neighbor_pos to respect the board's topology
     pub fn offset_pos(&self, pos: (usize, usize), direction: Direction) -> (usize, usize) {
         direction.offset_pos(pos, (self.width, self.height))
     }
 
+    pub fn neighbor_pos(
+        &self,
+        pos: (usize, usize),
+        direction: Direction,
+    ) -> Option<(usize, usize)> {
+        self.topology
+            .offset_pos(pos, direction, (self.width, self.height))
+    }
+
+    pub fn offset_pos_bounded(
+        &self,
+        pos: (usize, usize),
+        direction: Direction,
+    ) -> Option<(usize, usize)> {
+        direction.offset_pos_bounded(pos, (self.width, self.height))
+    }
+
     pub fn occupied_mask(&self) -> Vec<bool> {
-        self.board
+        self.occupied.clone()
+    }
+
+    // Same as occupied_mask, without the copy
+    pub fn occupied_cells(&self) -> &[bool] {
+        &self.occupied
+    }
+
+    pub fn head_positions(&self) -> Vec<(usize, usize)> {
+        self.players
             .iter()
-            .map(|&player_id| player_id != Self::NO_PLAYER)
+            .filter(|p| !p.dead)
+            .filter_map(|p| p.latest_pos)
             .collect()
     }
 
+    pub fn body_only_mask(&self, exclude_heads: bool) -> Vec<bool> {
+        let mut mask = self.occupied_mask();
+        if exclude_heads {
+            for (x, y) in self.head_positions() {
+                mask[y * self.width + x] = false;
+            }
+        }
+        mask
+    }
+
+    pub fn occupied_bit_mask(&self) -> BitMask {
+        let mut mask = BitMask::new(self.board.len());
+        for (i, &cell) in self.board.iter().enumerate() {
+            if cell != Self::NO_PLAYER {
+                mask.set(i, true);
+            }
+        }
+        mask
+    }
+
     pub fn conservative_occupied_mask(&self, own_player: usize) -> Vec<bool> {
         let mut mask = self.occupied_mask();
         for pos in self
@@ -119,11 +708,62 @@ impl BoardTracker {
             .filter(|(player_id, _)| *player_id != own_player)
             .filter_map(|(_, player)| player.latest_pos)
         {
-            for direction in Direction::all_directions() {
-                let new_pos = self.offset_pos(pos, direction);
+            for new_pos in Direction::all_directions()
+                .into_iter()
+                .filter_map(|direction| self.neighbor_pos(pos, direction))
+            {
                 mask[new_pos.1 * self.width + new_pos.0] = true;
             }
         }
         mask
     }
+
+    // Like conservative_occupied_mask, but ignores dead opponents. Nothing else
+    // can be pruned: occupied neighbors are in the mask anyway, and reversing
+    // always runs into the opponent's own trail.
+    pub fn conservative_occupied_mask_living(&self, own_player: usize) -> Vec<bool> {
+        let mut mask = self.occupied_mask();
+        for pos in self
+            .players
+            .iter()
+            .enumerate()
+            .filter(|(player_id, player)| *player_id != own_player && !player.dead)
+            .filter_map(|(_, player)| player.latest_pos)
+        {
+            for new_pos in Direction::all_directions()
+                .into_iter()
+                .filter_map(|direction| self.neighbor_pos(pos, direction))
+            {
+                mask[new_pos.1 * self.width + new_pos.0] = true;
+            }
+        }
+        mask
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("board snapshot is truncated"))?;
+        let taken = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize> {
+        let bytes = self.take(4)?.try_into().unwrap();
+        Ok(u32::from_le_bytes(bytes) as usize)
+    }
 }
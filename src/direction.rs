@@ -1,6 +1,10 @@
+use anyhow::anyhow;
 use core::fmt;
+use serde::Serialize;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Direction {
     Up,
     Right,
@@ -18,30 +22,63 @@ impl Direction {
         }
     }
 
+    pub fn turn_left(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Right => Direction::Up,
+            Direction::Down => Direction::Right,
+            Direction::Left => Direction::Down,
+        }
+    }
+
+    pub fn turn_right(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+
+    pub fn delta(&self) -> (isize, isize) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Right => (1, 0),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+        }
+    }
+
     pub fn offset_pos(&self, pos: (usize, usize), size: (usize, usize)) -> (usize, usize) {
         let (x, y) = pos;
         let (width, height) = size;
         assert!(x < width && y < height, "Invalid position: ({}, {})", x, y);
 
-        let x = (x as isize
-            + match self {
-                Direction::Left => -1,
-                Direction::Right => 1,
-                _ => 0,
-            }
-            + width as isize) as usize
-            % width;
-        let y = (y as isize
-            + match self {
-                Direction::Up => -1,
-                Direction::Down => 1,
-                _ => 0,
-            }
-            + height as isize) as usize
-            % height;
+        let (dx, dy) = self.delta();
+        let x = (x as isize + dx + width as isize) as usize % width;
+        let y = (y as isize + dy + height as isize) as usize % height;
         (x, y)
     }
 
+    pub fn offset_pos_bounded(
+        &self,
+        pos: (usize, usize),
+        size: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let (x, y) = pos;
+        let (width, height) = size;
+        assert!(x < width && y < height, "Invalid position: ({}, {})", x, y);
+
+        let (dx, dy) = self.delta();
+        let x = x as isize + dx;
+        let y = y as isize + dy;
+        if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+            None
+        } else {
+            Some((x as usize, y as usize))
+        }
+    }
+
     pub fn all_directions() -> [Direction; 4] {
         [
             Direction::Up,
@@ -62,3 +99,49 @@ impl fmt::Display for Direction {
         })
     }
 }
+
+impl FromStr for Direction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(Direction::Up),
+            "right" => Ok(Direction::Right),
+            "down" => Ok(Direction::Down),
+            "left" => Ok(Direction::Left),
+            _ => Err(anyhow!("invalid direction: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<&str> for Direction {
+    type Error = anyhow::Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// What happens when moving off an edge of the board. GPN Tron wraps around,
+// Bounded treats the edges as walls.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Topology {
+    #[default]
+    Torus,
+    Bounded,
+}
+
+impl Topology {
+    // None if the move would leave a bounded board
+    pub fn offset_pos(
+        self,
+        pos: (usize, usize),
+        direction: Direction,
+        size: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        match self {
+            Topology::Torus => Some(direction.offset_pos(pos, size)),
+            Topology::Bounded => direction.offset_pos_bounded(pos, size),
+        }
+    }
+}
@@ -1,6 +1,30 @@
-use crate::direction::Direction;
+use std::cmp::Reverse;
 
-pub fn calculate_distances(size: (usize, usize), occupied_mask: &[bool]) -> Vec<usize> {
+use crate::board_tracker::CellMask;
+use crate::direction::{Direction, Topology};
+
+pub fn calculate_distances(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+) -> Vec<usize> {
+    let (width, _height) = size;
+
+    let sources: Vec<(usize, usize)> = (0..occupied_mask.len())
+        .filter(|&i| occupied_mask.get(i))
+        .map(|i| (i % width, i / width))
+        .collect();
+    distances_from(size, topology, occupied_mask, &sources)
+}
+
+// Multi-source BFS that doesn't pass through occupied cells, except that the
+// sources themselves may be occupied. Unreachable cells get usize::MAX.
+pub fn distances_from(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+    sources: &[(usize, usize)],
+) -> Vec<usize> {
     let (width, height) = size;
 
     assert_eq!(occupied_mask.len(), width * height);
@@ -8,8 +32,9 @@ pub fn calculate_distances(size: (usize, usize), occupied_mask: &[bool]) -> Vec<
     let mut distances = vec![usize::MAX; width * height];
     let mut queue = std::collections::VecDeque::new();
 
-    for (i, &is_occupied) in occupied_mask.iter().enumerate() {
-        if is_occupied {
+    for &(x, y) in sources {
+        let i = y * width + x;
+        if distances[i] != 0 {
             distances[i] = 0;
             queue.push_back(i);
         }
@@ -20,11 +45,14 @@ pub fn calculate_distances(size: (usize, usize), occupied_mask: &[bool]) -> Vec<
         let pos = (current % width, current / width);
 
         for direction in Direction::all_directions() {
-            let new_pos = direction.offset_pos(pos, size);
+            let new_pos = match topology.offset_pos(pos, direction, size) {
+                Some(new_pos) => new_pos,
+                None => continue,
+            };
             let new_i = new_pos.1 * width + new_pos.0;
             let new_distance = current_distance + 1;
 
-            if new_distance < distances[new_i] {
+            if !occupied_mask.get(new_i) && new_distance < distances[new_i] {
                 distances[new_i] = new_distance;
                 queue.push_back(new_i);
             }
@@ -33,3 +61,52 @@ pub fn calculate_distances(size: (usize, usize), occupied_mask: &[bool]) -> Vec<
 
     distances
 }
+
+// Like distances_from, but entering cell i costs 1 + extra_costs[i], e.g. to
+// make cells next to opponent heads more expensive to pass through
+pub fn weighted_distances_from(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+    sources: &[(usize, usize)],
+    extra_costs: &[usize],
+) -> Vec<usize> {
+    let (width, height) = size;
+
+    assert_eq!(occupied_mask.len(), width * height);
+    assert_eq!(extra_costs.len(), width * height);
+
+    let mut distances = vec![usize::MAX; width * height];
+    let mut queue = std::collections::BinaryHeap::new();
+
+    for &(x, y) in sources {
+        let i = y * width + x;
+        if distances[i] != 0 {
+            distances[i] = 0;
+            queue.push(Reverse((0, i)));
+        }
+    }
+
+    while let Some(Reverse((current_distance, current))) = queue.pop() {
+        if current_distance > distances[current] {
+            continue;
+        }
+        let pos = (current % width, current / width);
+
+        for direction in Direction::all_directions() {
+            let new_pos = match topology.offset_pos(pos, direction, size) {
+                Some(new_pos) => new_pos,
+                None => continue,
+            };
+            let new_i = new_pos.1 * width + new_pos.0;
+            let new_distance = current_distance + 1 + extra_costs[new_i];
+
+            if !occupied_mask.get(new_i) && new_distance < distances[new_i] {
+                distances[new_i] = new_distance;
+                queue.push(Reverse((new_distance, new_i)));
+            }
+        }
+    }
+
+    distances
+}
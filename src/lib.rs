@@ -0,0 +1,3305 @@
+#![allow(dead_code)]
+
+use anyhow::anyhow;
+use anyhow::Result;
+use board_tracker::BoardTracker;
+use core::time;
+use direction::{Direction, Topology};
+use distance::{calculate_distances, distances_from, weighted_distances_from};
+use log::{debug, info, warn};
+use metrics::{DirectionScore, Heatmap, MetricsWriter, StepTrace};
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::{
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    net::{Shutdown, TcpStream},
+};
+
+mod articulation;
+pub mod board_tracker;
+pub mod direction;
+pub mod distance;
+mod metrics;
+mod playout;
+pub mod reachability;
+mod replay;
+pub mod shortest_path;
+mod shutdown;
+mod tournament;
+mod voronoi;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GameInfo {
+    width: i32,
+    height: i32,
+    player_id: i32,
+}
+
+impl GameInfo {
+    fn new_board(&self) -> Result<BoardTracker> {
+        if self.width <= 0 || self.height <= 0 {
+            return Err(anyhow!(
+                "invalid board size in game: {}x{}",
+                self.width,
+                self.height
+            ));
+        }
+        if self.player_id < 0 {
+            return Err(anyhow!("invalid player id in game: {}", self.player_id));
+        }
+        Ok(BoardTracker::new(
+            self.width.try_into().unwrap(),
+            self.height.try_into().unwrap(),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ServerMessage {
+    Motd { message: String },
+    Error { message: String },
+    Game { message: GameInfo },
+    Pos { player_id: i32, x: i32, y: i32 },
+    Player { player_id: i32, name: String },
+    Tick,
+    Die { player_ids: Vec<i32> },
+    Message { player_id: i32, message: String },
+    Win { wins: i32, losses: i32 },
+    Lose { wins: i32, losses: i32 },
+}
+
+impl ServerMessage {
+    fn parse(line: &str) -> Result<ServerMessage> {
+        // The last field of these is free text, which may itself contain "|"
+        let max_parts = match line.split('|').next().unwrap() {
+            "motd" | "error" => 2,
+            "player" | "message" => 3,
+            _ => usize::MAX,
+        };
+        let parts: Vec<_> = line.splitn(max_parts, '|').collect();
+        match parts[..] {
+            ["motd", message] => Ok(ServerMessage::Motd {
+                message: message.into(),
+            }),
+            ["error", message] => Ok(ServerMessage::Error {
+                message: message.into(),
+            }),
+            ["game", width, height, player_id] => Ok(ServerMessage::Game {
+                message: GameInfo {
+                    width: width.parse()?,
+                    height: height.parse()?,
+                    player_id: player_id.parse()?,
+                },
+            }),
+            ["pos", player_id, x, y] => Ok(ServerMessage::Pos {
+                player_id: player_id.parse()?,
+                x: x.parse()?,
+                y: y.parse()?,
+            }),
+            ["player", player_id, name] => Ok(ServerMessage::Player {
+                player_id: player_id.parse()?,
+                name: name.into(),
+            }),
+            ["tick"] => Ok(ServerMessage::Tick),
+            ["die", ..] => Ok(ServerMessage::Die {
+                player_ids: parts[1..]
+                    .iter()
+                    .map(|id| id.parse())
+                    .collect::<Result<_, _>>()?,
+            }),
+            ["message", player_id, message] => Ok(ServerMessage::Message {
+                player_id: player_id.parse()?,
+                message: message.into(),
+            }),
+            ["win", wins, losses] => Ok(ServerMessage::Win {
+                wins: wins.parse()?,
+                losses: losses.parse()?,
+            }),
+            ["lose", wins, losses] => Ok(ServerMessage::Lose {
+                wins: wins.parse()?,
+                losses: losses.parse()?,
+            }),
+            _ => Err(anyhow!("invalid message: {}", line)),
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            ServerMessage::Motd { message } => format!("motd|{}", message),
+            ServerMessage::Error { message } => format!("error|{}", message),
+            ServerMessage::Game { message } => format!(
+                "game|{}|{}|{}",
+                message.width, message.height, message.player_id
+            ),
+            ServerMessage::Pos { player_id, x, y } => format!("pos|{}|{}|{}", player_id, x, y),
+            ServerMessage::Player { player_id, name } => format!("player|{}|{}", player_id, name),
+            ServerMessage::Tick => "tick".to_string(),
+            ServerMessage::Die { player_ids } => std::iter::once("die".to_string())
+                .chain(player_ids.iter().map(|id| id.to_string()))
+                .collect::<Vec<_>>()
+                .join("|"),
+            ServerMessage::Message { player_id, message } => {
+                format!("message|{}|{}", player_id, message)
+            }
+            ServerMessage::Win { wins, losses } => format!("win|{}|{}", wins, losses),
+            ServerMessage::Lose { wins, losses } => format!("lose|{}|{}", wins, losses),
+        }
+    }
+}
+
+// Whatever we could make of the motd. The server doesn't promise any format,
+// so only "key=value" or "key: value" pairs separated by "," or ";" are
+// recognized, and everything else is left in raw.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ServerInfo {
+    raw: String,
+    version: Option<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl ServerInfo {
+    fn from_motd(motd: &str) -> Self {
+        let fields: Vec<(String, String)> = motd
+            .split([',', ';'])
+            .filter_map(|part| {
+                let (key, value) = part.split_once(['=', ':'])?;
+                let (key, value) = (key.trim(), value.trim());
+                if key.is_empty() || key.contains(char::is_whitespace) || value.is_empty() {
+                    return None;
+                }
+                Some((key.to_lowercase(), value.to_string()))
+            })
+            .collect();
+        let version = fields
+            .iter()
+            .find(|(key, _)| key == "version")
+            .map(|(_, value)| value.clone());
+        Self {
+            raw: motd.to_string(),
+            version,
+            fields,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ClientMessage {
+    // The official server has no rooms, so room is only sent when set
+    Join {
+        username: String,
+        password: String,
+        room: Option<String>,
+    },
+    Move {
+        direction: Direction,
+    },
+    Chat {
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+enum ReadError {
+    // The server closed the connection between messages
+    Eof,
+    // The server closed the connection in the middle of a message
+    PartialLine(String),
+    Timeout,
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::Eof => write!(f, "connection closed by server"),
+            ReadError::PartialLine(line) => {
+                write!(f, "connection closed in the middle of a line: {:?}", line)
+            }
+            ReadError::Timeout => write!(f, "timed out waiting for server"),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+struct GameReader<R: Read> {
+    inner: BufReader<R>,
+    line: String,
+}
+
+impl<R: Read> GameReader<R> {
+    fn new(inner: R) -> GameReader<R> {
+        GameReader {
+            inner: BufReader::new(inner),
+            line: String::new(),
+        }
+    }
+
+    fn read(&mut self) -> Result<ServerMessage> {
+        self.line.clear();
+        let bytes_read = match self.inner.read_line(&mut self.line) {
+            Ok(bytes_read) => bytes_read,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(ReadError::Timeout.into());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if bytes_read == 0 {
+            return Err(ReadError::Eof.into());
+        }
+        if !self.line.ends_with('\n') {
+            return Err(ReadError::PartialLine(self.line.clone()).into());
+        }
+        self.line.pop();
+
+        ServerMessage::parse(&self.line)
+    }
+
+    // Only looks at what's already buffered, so this never blocks. A trailing
+    // partial line stays buffered for the next read.
+    fn read_all_available(&mut self) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+        while self.inner.buffer().contains(&b'\n') {
+            match self.read() {
+                Ok(msg) => messages.push(msg),
+                Err(err) => warn!("skipping buffered message: {:#}", err),
+            }
+        }
+        messages
+    }
+}
+
+struct GameWriter<W: Write> {
+    inner: BufWriter<W>,
+}
+
+impl<W: Write> GameWriter<W> {
+    fn new(inner: W) -> GameWriter<W> {
+        GameWriter {
+            inner: BufWriter::new(inner),
+        }
+    }
+
+    // Longer chat messages are cut off rather than risking the server dropping them
+    const MAX_CHAT_CHARS: usize = 100;
+
+    fn write(&mut self, message: &ClientMessage) -> Result<()> {
+        self.write_no_flush(message)?;
+        self.flush()
+    }
+
+    fn write_no_flush(&mut self, message: &ClientMessage) -> Result<()> {
+        self.inner
+            .write_all(Self::encode_message(message)?.as_bytes())?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    fn encode_message(message: &ClientMessage) -> Result<String> {
+        Ok(match message {
+            ClientMessage::Join {
+                username,
+                password,
+                room,
+            } => {
+                check_field("username", username)?;
+                check_field("password", password)?;
+                match room {
+                    Some(room) => {
+                        check_field("room", room)?;
+                        format!("join|{}|{}|{}\n", username, password, room)
+                    }
+                    None => format!("join|{}|{}\n", username, password),
+                }
+            }
+            ClientMessage::Move { direction } => format!("move|{}\n", direction),
+            ClientMessage::Chat { message } => {
+                check_field("chat message", message)?;
+                let message: String = message.chars().take(Self::MAX_CHAT_CHARS).collect();
+                format!("chat|{}\n", message)
+            }
+        })
+    }
+}
+
+// A "|" or newline inside a field would break the protocol framing
+fn check_field(name: &str, value: &str) -> Result<()> {
+    if value.contains(['|', '\n']) {
+        return Err(anyhow!(
+            "{} must not contain '|' or newlines: {:?}",
+            name,
+            value
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StepDecision {
+    direction: Direction,
+    // Mean playout score of the chosen direction, so its scale depends on the
+    // strategy's scoring mode. 0 for strategies that don't run playouts.
+    confidence: f64,
+    playouts: usize,
+}
+
+impl StepDecision {
+    fn without_playouts(direction: Direction) -> Self {
+        Self {
+            direction,
+            confidence: 0.0,
+            playouts: 0,
+        }
+    }
+
+    fn from_trace(direction: Direction, trace: Option<&StepTrace>) -> Self {
+        let trace = match trace {
+            Some(trace) => trace,
+            None => return Self::without_playouts(direction),
+        };
+        let confidence = trace
+            .direction_scores
+            .iter()
+            .find(|score| score.direction == direction)
+            .map_or(0.0, |score| score.mean_score);
+        Self {
+            direction,
+            confidence,
+            playouts: trace.playouts,
+        }
+    }
+}
+
+// Where we are in the round, for strategies that play differently over time
+#[derive(Debug, Clone, Copy, Default)]
+struct StepContext {
+    // Ticks since the game started, 0 for the first move
+    tick: usize,
+    round_elapsed: Duration,
+}
+
+trait Strategy {
+    fn start(&mut self, game_info: &GameInfo);
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction;
+
+    // Only online play has a motd, everywhere else this is just start
+    fn start_with_server_info(&mut self, game_info: &GameInfo, _server_info: &ServerInfo) {
+        self.start(game_info);
+    }
+
+    fn step_in_context(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        _context: &StepContext,
+    ) -> Direction {
+        self.step(board, time_budget)
+    }
+
+    fn step_detailed(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> StepDecision {
+        StepDecision::without_playouts(self.step_in_context(board, time_budget, context))
+    }
+
+    // Details about the last step, for strategies that have any
+    fn take_trace(&mut self) -> Option<StepTrace> {
+        None
+    }
+}
+
+impl<S: Strategy + ?Sized> Strategy for Box<S> {
+    fn start(&mut self, game_info: &GameInfo) {
+        (**self).start(game_info);
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        (**self).step(board, time_budget)
+    }
+
+    fn start_with_server_info(&mut self, game_info: &GameInfo, server_info: &ServerInfo) {
+        (**self).start_with_server_info(game_info, server_info);
+    }
+
+    fn step_in_context(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> Direction {
+        (**self).step_in_context(board, time_budget, context)
+    }
+
+    fn step_detailed(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> StepDecision {
+        (**self).step_detailed(board, time_budget, context)
+    }
+
+    fn take_trace(&mut self) -> Option<StepTrace> {
+        (**self).take_trace()
+    }
+}
+
+// Keeps "every move crashes" apart from a normal pick, so that logs show
+// whether a death was forced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MoveChoice {
+    Safe(Direction),
+    // Straight ahead if we have a heading, since it doesn't matter where we crash
+    ForcedCrash(Direction),
+}
+
+impl MoveChoice {
+    fn safe_or_forced_crash(
+        board: &BoardTracker,
+        player_id: usize,
+        safe: Option<Direction>,
+    ) -> Self {
+        match safe {
+            Some(direction) => MoveChoice::Safe(direction),
+            None => MoveChoice::ForcedCrash(
+                board
+                    .get_player_heading(player_id)
+                    .unwrap_or(Direction::Down),
+            ),
+        }
+    }
+
+    // Playouts crash all the time, so this doesn't log
+    fn direction(self) -> Direction {
+        match self {
+            MoveChoice::Safe(direction) | MoveChoice::ForcedCrash(direction) => direction,
+        }
+    }
+
+    fn logged_direction(self) -> Direction {
+        if let MoveChoice::ForcedCrash(direction) = self {
+            info!("no safe move, crashing {}", direction);
+        }
+        self.direction()
+    }
+}
+
+struct AlwaysDownStrategy {}
+
+impl AlwaysDownStrategy {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Strategy for AlwaysDownStrategy {
+    fn start(&mut self, _game_info: &GameInfo) {}
+
+    fn step(&mut self, _board: &BoardTracker, _time_budget: Duration) -> Direction {
+        Direction::Down
+    }
+}
+
+struct NoCrashRandomStrategy {
+    player_id: usize,
+    rng: StdRng,
+}
+
+impl NoCrashRandomStrategy {
+    fn new(rng: StdRng) -> Self {
+        Self { player_id: 0, rng }
+    }
+}
+
+impl Strategy for NoCrashRandomStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+    }
+
+    fn step(&mut self, board: &BoardTracker, _time_budget: Duration) -> Direction {
+        let safe = board
+            .safe_moves(self.player_id)
+            .choose(&mut self.rng)
+            .copied();
+        MoveChoice::safe_or_forced_crash(board, self.player_id, safe).direction()
+    }
+}
+
+// Like NoCrashRandomStrategy, but avoids moving into small dead ends, which
+// makes playouts look more like real games
+struct SafeishRandomStrategy {
+    player_id: usize,
+    rng: StdRng,
+}
+
+impl SafeishRandomStrategy {
+    const FLOOD_FILL_CAP: usize = 16;
+
+    fn new(rng: StdRng) -> Self {
+        Self { player_id: 0, rng }
+    }
+}
+
+impl Strategy for SafeishRandomStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+    }
+
+    fn step(&mut self, board: &BoardTracker, _time_budget: Duration) -> Direction {
+        let player_pos = board.get_player_latest_pos(self.player_id).unwrap();
+
+        let weighted_directions: Vec<(Direction, usize)> = board
+            .safe_moves(self.player_id)
+            .into_iter()
+            .map(|direction| {
+                let reachable_count = reachability::count_reachable_capped(
+                    board.board_size(),
+                    board.topology(),
+                    board,
+                    board.neighbor_pos(player_pos, direction).unwrap(),
+                    Self::FLOOD_FILL_CAP,
+                );
+                (direction, reachable_count)
+            })
+            .collect();
+
+        let safe = weighted_directions
+            .choose_weighted(&mut self.rng, |&(_, weight)| weight)
+            .ok()
+            .map(|&(direction, _)| direction);
+        MoveChoice::safe_or_forced_crash(board, self.player_id, safe).direction()
+    }
+}
+
+// Keeps going straight with straight_prob if that's safe, otherwise leaves
+// the move to other_strategy
+struct MomentumRandomStrategy<T: Strategy> {
+    player_id: usize,
+    straight_prob: f64,
+    other_strategy: T,
+    rng: StdRng,
+}
+
+impl<T: Strategy> MomentumRandomStrategy<T> {
+    fn new(straight_prob: f64, other_strategy: T, rng: StdRng) -> Self {
+        assert!((0.0..=1.0).contains(&straight_prob));
+        Self {
+            player_id: 0,
+            straight_prob,
+            other_strategy,
+            rng,
+        }
+    }
+}
+
+impl<T: Strategy> Strategy for MomentumRandomStrategy<T> {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+        self.other_strategy.start(game_info);
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        if let Some(heading) = board.get_player_heading(self.player_id) {
+            if self.rng.gen::<f64>() < self.straight_prob
+                && board.safe_moves(self.player_id).contains(&heading)
+            {
+                return heading;
+            }
+        }
+
+        self.other_strategy.step(board, time_budget)
+    }
+}
+
+struct ConstantThenOtherStrategy<T: Strategy> {
+    did_first_step: bool,
+    first_direction: Direction,
+    other_strategy: T,
+}
+
+impl<T: Strategy> ConstantThenOtherStrategy<T> {
+    fn new(first_direction: Direction, other_strategy: T) -> Self {
+        Self {
+            did_first_step: false,
+            first_direction,
+            other_strategy,
+        }
+    }
+}
+
+impl<T: Strategy> Strategy for ConstantThenOtherStrategy<T> {
+    fn start(&mut self, game_info: &GameInfo) {
+        assert!(!self.did_first_step);
+        self.other_strategy.start(game_info);
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        if self.did_first_step {
+            self.other_strategy.step(board, time_budget)
+        } else {
+            self.did_first_step = true;
+            self.first_direction
+        }
+    }
+}
+
+struct GetAwayFromItAllStrategy {
+    player_id: usize,
+    // How much free space around a target counts compared to its distance
+    // from occupied cells, so that we don't flee into dead corners
+    open_space_weight: f64,
+    // Extra cost of stepping next to a living opponent's head on the way to a
+    // target. Zero ignores opponents apart from the cells they've taken.
+    head_proximity_cost: usize,
+    rng: StdRng,
+}
+
+impl GetAwayFromItAllStrategy {
+    const OPEN_SPACE_RADIUS: usize = 3;
+    // The path to a target can cut us off from the rest of our area, so the
+    // first step has to leave this much room (or all of it, if we have less)
+    const MIN_ROOM_AFTER_STEP: usize = 16;
+    const MAX_TARGET_TRIES: usize = 8;
+
+    fn new(rng: StdRng) -> Self {
+        Self {
+            player_id: 0,
+            open_space_weight: 0.5,
+            head_proximity_cost: 0,
+            rng,
+        }
+    }
+
+    fn with_open_space_weight(mut self, open_space_weight: f64) -> Self {
+        assert!(open_space_weight >= 0.0);
+        self.open_space_weight = open_space_weight;
+        self
+    }
+
+    fn with_head_proximity_cost(mut self, head_proximity_cost: usize) -> Self {
+        self.head_proximity_cost = head_proximity_cost;
+        self
+    }
+
+    // How much more it costs to reach each cell when passing next to opponent
+    // heads is expensive, compared to the plain path length
+    fn head_proximity_detours(&self, board: &BoardTracker) -> Vec<usize> {
+        let (width, _height) = board.board_size();
+        let player_pos = board.get_player_latest_pos(self.player_id).unwrap();
+
+        let mut extra_costs = vec![0; board.occupied_cells().len()];
+        for head in board.head_positions() {
+            if head == player_pos {
+                continue;
+            }
+            for new_pos in Direction::all_directions()
+                .into_iter()
+                .filter_map(|direction| board.neighbor_pos(head, direction))
+            {
+                extra_costs[new_pos.1 * width + new_pos.0] = self.head_proximity_cost;
+            }
+        }
+
+        let sources = [player_pos];
+        let plain = distances_from(
+            board.board_size(),
+            board.topology(),
+            board.occupied_cells(),
+            &sources,
+        );
+        let weighted = weighted_distances_from(
+            board.board_size(),
+            board.topology(),
+            board.occupied_cells(),
+            &sources,
+            &extra_costs,
+        );
+        weighted
+            .iter()
+            .zip(plain)
+            .map(|(&weighted, plain)| weighted.saturating_sub(plain))
+            .collect()
+    }
+}
+
+impl Strategy for GetAwayFromItAllStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+    }
+
+    fn step(&mut self, board: &BoardTracker, _time_budget: Duration) -> Direction {
+        let (width, _height) = board.board_size();
+        let player_pos = board.get_player_latest_pos(self.player_id).unwrap();
+
+        let occupied_mask = board.occupied_cells();
+        let conservative_occupied_mask = board.conservative_occupied_mask(self.player_id);
+        let distances = calculate_distances(board.board_size(), board.topology(), occupied_mask);
+        assert_eq!(distances.len(), occupied_mask.len());
+        let reachable_mask = reachability::calculate_reachable(
+            board.board_size(),
+            board.topology(),
+            &conservative_occupied_mask,
+            player_pos,
+        );
+        assert_eq!(reachable_mask.len(), occupied_mask.len());
+        let detours = if self.head_proximity_cost > 0 {
+            Some(self.head_proximity_detours(board))
+        } else {
+            None
+        };
+
+        let mut targets: Vec<((usize, usize), f64)> = Vec::new();
+        for pos in board.all_cells() {
+            let i = pos.1 * width + pos.0;
+            if pos == player_pos || !reachable_mask[i] {
+                continue;
+            }
+            let open_space = if self.open_space_weight > 0.0 {
+                reachability::count_reachable_within(
+                    board.board_size(),
+                    board.topology(),
+                    &conservative_occupied_mask,
+                    pos,
+                    Self::OPEN_SPACE_RADIUS,
+                )
+            } else {
+                0
+            };
+            let detour = detours.as_ref().map_or(0, |detours| detours[i]);
+            let score =
+                distances[i] as f64 + self.open_space_weight * open_space as f64 - detour as f64;
+            targets.push((pos, score));
+        }
+        if targets.is_empty() {
+            warn!("no best target found");
+
+            let safe = board
+                .safe_moves(self.player_id)
+                .choose(&mut self.rng)
+                .copied();
+            return MoveChoice::safe_or_forced_crash(board, self.player_id, safe)
+                .logged_direction();
+        }
+        // Stable, so that equal scores keep the row by row order
+        targets.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+
+        let required_room = Self::MIN_ROOM_AFTER_STEP.min(targets.len());
+        let mut most_room: Option<(Direction, usize)> = None;
+        for &(target, _) in targets.iter().take(Self::MAX_TARGET_TRIES) {
+            let direction = shortest_path::shortest_path_next_direction(
+                board.board_size(),
+                board.topology(),
+                &conservative_occupied_mask,
+                player_pos,
+                target,
+            )
+            .unwrap();
+            let room = reachability::count_reachable_capped(
+                board.board_size(),
+                board.topology(),
+                &conservative_occupied_mask,
+                board.neighbor_pos(player_pos, direction).unwrap(),
+                required_room,
+            );
+            if room >= required_room {
+                return direction;
+            }
+            if most_room.is_none_or(|(_, most)| room > most) {
+                most_room = Some((direction, room));
+            }
+        }
+        debug!("no getaway target leaves enough room, taking the roomiest step");
+        most_room.unwrap().0
+    }
+}
+
+struct SpaceFillStrategy {
+    player_id: usize,
+}
+
+impl SpaceFillStrategy {
+    fn new() -> Self {
+        Self { player_id: 0 }
+    }
+}
+
+impl Strategy for SpaceFillStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+    }
+
+    fn step(&mut self, board: &BoardTracker, _time_budget: Duration) -> Direction {
+        let (width, _height) = board.board_size();
+        let player_pos = board.get_player_latest_pos(self.player_id).unwrap();
+
+        let occupied_mask = board.occupied_cells();
+        let mut conservative_occupied_mask = board.conservative_occupied_mask(self.player_id);
+
+        // Prefer the most space left afterwards, then moves that opponents can't
+        // also move into, then the most walls around the new cell so that we
+        // don't split our own area in two
+        let mut best: Option<(Direction, (usize, bool, usize))> = None;
+        for direction in Direction::all_directions() {
+            let new_pos = match board.neighbor_pos(player_pos, direction) {
+                Some(new_pos) => new_pos,
+                None => continue,
+            };
+            let new_i = new_pos.1 * width + new_pos.0;
+            if occupied_mask[new_i] || board.is_reverse_move(self.player_id, direction) {
+                continue;
+            }
+
+            let uncontested = !conservative_occupied_mask[new_i];
+            conservative_occupied_mask[new_i] = true;
+            let (_, reachable_count) = reachability::calculate_reachable_with_count(
+                board.board_size(),
+                board.topology(),
+                &conservative_occupied_mask,
+                new_pos,
+            );
+            conservative_occupied_mask[new_i] = !uncontested;
+
+            let wall_count = Direction::all_directions()
+                .iter()
+                .filter(|&&wall_direction| {
+                    // The edge of a bounded board counts as a wall
+                    board
+                        .neighbor_pos(new_pos, wall_direction)
+                        .is_none_or(|wall_pos| occupied_mask[wall_pos.1 * width + wall_pos.0])
+                })
+                .count();
+
+            let key = (reachable_count, uncontested, wall_count);
+            if best.is_none_or(|(_, best_key)| key > best_key) {
+                best = Some((direction, key));
+            }
+        }
+
+        let safe = best.map(|(direction, _)| direction);
+        MoveChoice::safe_or_forced_crash(board, self.player_id, safe).logged_direction()
+    }
+}
+
+// Follows walls with the right-hand rule. Trails are the walls, plus the
+// edges on a bounded board.
+struct WallHugStrategy {
+    player_id: usize,
+}
+
+impl WallHugStrategy {
+    fn new() -> Self {
+        Self { player_id: 0 }
+    }
+}
+
+impl Strategy for WallHugStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+    }
+
+    fn step(&mut self, board: &BoardTracker, _time_budget: Duration) -> Direction {
+        let player_pos = board.get_player_latest_pos(self.player_id).unwrap();
+        let heading = board
+            .get_player_heading(self.player_id)
+            .unwrap_or(Direction::Up);
+        // Off the edge of a bounded board counts as taken
+        let is_free = |pos: Option<(usize, usize)>| {
+            pos.is_some_and(|pos| board.get_cell_player(pos).is_none())
+        };
+
+        let free_directions: Vec<Direction> = [heading.turn_right(), heading, heading.turn_left()]
+            .into_iter()
+            .filter(|&direction| is_free(board.neighbor_pos(player_pos, direction)))
+            .collect();
+
+        let keeps_wall_on_right = |direction: Direction| {
+            let new_pos = board.neighbor_pos(player_pos, direction).unwrap();
+            !is_free(board.neighbor_pos(new_pos, direction.turn_right()))
+        };
+        if let Some(&direction) = free_directions
+            .iter()
+            .find(|&&direction| keeps_wall_on_right(direction))
+        {
+            return direction;
+        }
+
+        MoveChoice::safe_or_forced_crash(board, self.player_id, free_directions.first().copied())
+            .logged_direction()
+    }
+}
+
+// With 2+ opponents, goes after the one with the least room by heading for
+// the nearest cell of its region, which cuts the region down further. Falls
+// back to SpaceFill when there's nobody to hunt or no safe way there.
+struct HuntWeakestStrategy {
+    player_id: usize,
+    fallback_strategy: SpaceFillStrategy,
+}
+
+impl HuntWeakestStrategy {
+    fn new() -> Self {
+        Self {
+            player_id: 0,
+            fallback_strategy: SpaceFillStrategy::new(),
+        }
+    }
+
+    // The reachable cells of the living opponent with the fewest of them
+    fn weakest_opponent_region(&self, board: &BoardTracker) -> Option<Vec<(usize, usize)>> {
+        let mut opponent_regions: Vec<Vec<(usize, usize)>> = (0..board.count_seen())
+            .filter(|&player_id| player_id != self.player_id && !board.is_dead(player_id))
+            .filter_map(|player_id| {
+                let head = board.get_player_latest_pos(player_id)?;
+                let reachable_mask = reachability::calculate_reachable(
+                    board.board_size(),
+                    board.topology(),
+                    &board.conservative_occupied_mask(player_id),
+                    head,
+                );
+                Some(
+                    board
+                        .all_cells()
+                        .filter(|&(x, y)| {
+                            reachable_mask[y * board.board_size().0 + x] && (x, y) != head
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+        if opponent_regions.len() < 2 {
+            return None;
+        }
+        opponent_regions.sort_by_key(|region| region.len());
+        opponent_regions.into_iter().next()
+    }
+}
+
+impl Strategy for HuntWeakestStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+        self.fallback_strategy.start(game_info);
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        let player_pos = board.get_player_latest_pos(self.player_id).unwrap();
+        let direction = self.weakest_opponent_region(board).and_then(|targets| {
+            shortest_path::shortest_path_to_any(
+                board.board_size(),
+                board.topology(),
+                &board.conservative_occupied_mask(self.player_id),
+                player_pos,
+                &targets,
+            )
+        });
+        match direction {
+            Some(direction) if board.safe_moves(self.player_id).contains(&direction) => direction,
+            _ => self.fallback_strategy.step(board, time_budget),
+        }
+    }
+}
+
+struct VoronoiGreedyStrategy {
+    player_id: usize,
+    fallback_strategy: SpaceFillStrategy,
+}
+
+impl VoronoiGreedyStrategy {
+    fn new() -> Self {
+        Self {
+            player_id: 0,
+            fallback_strategy: SpaceFillStrategy::new(),
+        }
+    }
+}
+
+impl Strategy for VoronoiGreedyStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+        self.fallback_strategy.start(game_info);
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        let safe_moves = board.safe_moves(self.player_id);
+        if safe_moves.is_empty() {
+            return self.fallback_strategy.step(board, time_budget);
+        }
+
+        let player_pos = board.get_player_latest_pos(self.player_id).unwrap();
+        // Our head goes first, so it's index 0 in the Voronoi owners
+        let opponent_heads: Vec<_> = (0..board.count_seen())
+            .filter(|&player_id| player_id != self.player_id && !board.is_dead(player_id))
+            .filter_map(|player_id| board.get_player_latest_pos(player_id))
+            .collect();
+        let mut occupied_mask = board.occupied_mask();
+        let scores: Vec<(Direction, f64)> = safe_moves
+            .into_iter()
+            .map(|direction| {
+                let new_pos = board.neighbor_pos(player_pos, direction).unwrap();
+                let new_i = new_pos.1 * board.board_size().0 + new_pos.0;
+                occupied_mask[new_i] = true;
+                let mut heads = vec![new_pos];
+                heads.extend_from_slice(&opponent_heads);
+                let owners = voronoi::voronoi_control(
+                    board.board_size(),
+                    board.topology(),
+                    &occupied_mask,
+                    &heads,
+                );
+                occupied_mask[new_i] = false;
+                (direction, voronoi::count_controlled(&owners, 0) as f64)
+            })
+            .collect();
+        best_direction_with_space_tiebreak(board, self.player_id, &scores)
+    }
+}
+
+fn child_rng(rng: &mut StdRng) -> StdRng {
+    StdRng::seed_from_u64(rng.gen())
+}
+
+fn run_random_playout(
+    board: &mut BoardTracker,
+    rng: &mut StdRng,
+    own_player_id: usize,
+    own_start_direction: Direction,
+    options: playout::PlayoutOptions,
+    territory_cache: Option<&Mutex<playout::TerritoryCache>>,
+    opponent_straight_prob: f64,
+) -> playout::PlayoutResult {
+    let strategies_by_player: Vec<_> = (0..board.count_seen())
+        .map(|player_id| {
+            let fake_game_info = GameInfo {
+                width: 0,
+                height: 0,
+                player_id: player_id.try_into().unwrap(),
+            };
+            let base_strategy = SafeishRandomStrategy::new(child_rng(rng));
+            let mut strategy: Box<dyn Strategy> =
+                if board.get_player_latest_pos(player_id).is_none() {
+                    // Never stepped, since run_turn skips players without a position
+                    Box::new(AlwaysDownStrategy::new())
+                } else if player_id == own_player_id {
+                    Box::new(ConstantThenOtherStrategy::new(
+                        own_start_direction,
+                        base_strategy,
+                    ))
+                } else {
+                    Box::new(MomentumRandomStrategy::new(
+                        opponent_straight_prob,
+                        base_strategy,
+                        child_rng(rng),
+                    ))
+                };
+            strategy.start(&fake_game_info);
+            strategy
+        })
+        .collect();
+
+    playout::run_playout(
+        board,
+        strategies_by_player,
+        own_player_id,
+        options,
+        territory_cache,
+    )
+}
+
+// For benches/, which can't reach the strategies. Runs one playout like the
+// ones PlayoutAfterNextStrategy::step repeats until its budget is used up,
+// since timing step itself would only measure the budget. Returns the number
+// of steps we survived.
+pub fn bench_playout(
+    board: &mut BoardTracker,
+    own_player_id: usize,
+    max_steps: usize,
+    seed: u64,
+) -> usize {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let own_start_direction = board
+        .safe_moves(own_player_id)
+        .first()
+        .copied()
+        .unwrap_or(Direction::Down);
+    let options = playout::PlayoutOptions::new(max_steps, false);
+    run_random_playout(
+        board,
+        &mut rng,
+        own_player_id,
+        own_start_direction,
+        options,
+        None,
+        0.0,
+    )
+    .survived_steps
+}
+
+// Between a win and a loss, since at least nobody else won
+const PLAYOUT_DRAW_SCORE: f64 = 0.5;
+
+// territory_weight blends in our territory share for playouts that didn't
+// finish, since 1 / remaining_players says nothing about how well we're doing
+fn playout_win_score(playout_result: &playout::PlayoutResult, territory_weight: f64) -> f64 {
+    if playout_result.did_win {
+        1.0
+    } else if playout_result.did_draw {
+        PLAYOUT_DRAW_SCORE
+    } else if playout_result.did_die {
+        0.0
+    } else {
+        assert!(playout_result.remaining_players > 0);
+        let fractional_score = 1.0 / (playout_result.remaining_players as f64);
+        match playout_result.territory_share {
+            Some(territory_share) => {
+                (1.0 - territory_weight) * fractional_score + territory_weight * territory_share
+            }
+            None => fractional_score,
+        }
+    }
+}
+
+enum PlayoutAfterNextStrategyMode {
+    WinProbability,
+    SurvivedSteps,
+    SurviveMoreThanWin,
+}
+
+struct PlayoutAfterNextStrategy {
+    player_id: usize,
+    mode: PlayoutAfterNextStrategyMode,
+    max_steps: usize,
+    win_multiplier: usize,
+    territory_weight: f64,
+    stop_on_territory_majority: bool,
+    clear_on_death_prob: f32,
+    rng: StdRng,
+    last_trace: Option<StepTrace>,
+    // Below this budget a handful of playouts is mostly noise, so we use the
+    // fallback strategy instead
+    min_playout_budget: Duration,
+    fallback_strategy: SpaceFillStrategy,
+    stop_on_separation: bool,
+    // Add a heatmap of where our playouts went to the trace
+    record_heatmap: bool,
+    // Shared by all playouts of a step, only used with a territory_weight
+    territory_cache: Option<Mutex<playout::TerritoryCache>>,
+    // Give directions with more room after the move more playouts
+    reachability_seeding: bool,
+    // How often playout opponents keep their heading when they safely can
+    opponent_straight_prob: f64,
+}
+
+impl PlayoutAfterNextStrategy {
+    const MAX_SEEDED_PLAYOUTS_PER_ROUND: usize = 4;
+
+    fn new(
+        mode: PlayoutAfterNextStrategyMode,
+        max_steps: usize,
+        win_multiplier: usize,
+        territory_weight: f64,
+        stop_on_territory_majority: bool,
+        clear_on_death_prob: f32,
+        rng: StdRng,
+    ) -> Self {
+        assert!(max_steps > 0);
+        assert!(win_multiplier > 0);
+        assert!((0.0..=1.0).contains(&territory_weight));
+        assert!((0.0..=1.0).contains(&clear_on_death_prob));
+        Self {
+            player_id: 0,
+            mode,
+            max_steps,
+            win_multiplier,
+            territory_weight,
+            stop_on_territory_majority,
+            clear_on_death_prob,
+            rng,
+            last_trace: None,
+            min_playout_budget: Duration::ZERO,
+            fallback_strategy: SpaceFillStrategy::new(),
+            stop_on_separation: false,
+            record_heatmap: false,
+            territory_cache: None,
+            reachability_seeding: false,
+            opponent_straight_prob: 0.0,
+        }
+    }
+
+    fn with_min_playout_budget(mut self, min_playout_budget: Duration) -> Self {
+        self.min_playout_budget = min_playout_budget;
+        self
+    }
+
+    fn with_stop_on_separation(mut self, stop_on_separation: bool) -> Self {
+        self.stop_on_separation = stop_on_separation;
+        self
+    }
+
+    fn with_heatmap(mut self, record_heatmap: bool) -> Self {
+        self.record_heatmap = record_heatmap;
+        self
+    }
+
+    fn with_territory_cache(mut self, use_territory_cache: bool) -> Self {
+        self.territory_cache = use_territory_cache.then(Default::default);
+        self
+    }
+
+    fn with_reachability_seeding(mut self, reachability_seeding: bool) -> Self {
+        self.reachability_seeding = reachability_seeding;
+        self
+    }
+
+    fn with_opponent_straight_prob(mut self, opponent_straight_prob: f64) -> Self {
+        assert!((0.0..=1.0).contains(&opponent_straight_prob));
+        self.opponent_straight_prob = opponent_straight_prob;
+        self
+    }
+
+    // Playouts per round for each direction. A direction into a pocket still
+    // gets one, in case the pocket opens up when someone dies.
+    fn playouts_per_round(&self, board: &BoardTracker, directions: &[Direction]) -> Vec<usize> {
+        if !self.reachability_seeding {
+            return vec![1; directions.len()];
+        }
+        let head = board.get_player_latest_pos(self.player_id).unwrap();
+        let reachable = reachability::reachable_after_each_move(
+            board.board_size(),
+            board.topology(),
+            board,
+            head,
+        );
+        let reachable_by_direction: Vec<usize> = directions
+            .iter()
+            .map(|&direction| {
+                let i = Direction::all_directions()
+                    .iter()
+                    .position(|&d| d == direction)
+                    .unwrap();
+                reachable[i].unwrap_or(0)
+            })
+            .collect();
+        let max_reachable = reachable_by_direction
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        reachable_by_direction
+            .into_iter()
+            .map(|reachable| {
+                (Self::MAX_SEEDED_PLAYOUTS_PER_ROUND * reachable / max_reachable).max(1)
+            })
+            .collect()
+    }
+}
+
+impl Strategy for PlayoutAfterNextStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+        self.fallback_strategy.start(game_info);
+    }
+
+    fn take_trace(&mut self) -> Option<StepTrace> {
+        self.last_trace.take()
+    }
+
+    fn step_detailed(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> StepDecision {
+        let direction = self.step_in_context(board, time_budget, context);
+        StepDecision::from_trace(direction, self.last_trace.as_ref())
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        let step_start = Instant::now();
+        self.last_trace = None;
+
+        let n_players = board.count_seen();
+        assert!(n_players > 0);
+        assert!(self.player_id < n_players);
+
+        let mut no_crash_directions = board.safe_moves(self.player_id);
+        no_crash_directions.shuffle(&mut self.rng);
+
+        if no_crash_directions.is_empty() {
+            return MoveChoice::safe_or_forced_crash(board, self.player_id, None)
+                .logged_direction();
+        }
+        if no_crash_directions.len() == 1 {
+            return no_crash_directions[0];
+        }
+        if time_budget < self.min_playout_budget {
+            debug!(
+                "{} ms budget is too small for playouts, using fallback",
+                time_budget.as_millis()
+            );
+            return self.fallback_strategy.step(board, time_budget);
+        }
+
+        #[derive(Clone, Debug)]
+        struct DirectionStats {
+            direction: Direction,
+            score_survive: f64,
+            score_win: f64,
+            playouts: usize,
+        }
+        let mut stats_by_direction: Vec<_> = no_crash_directions
+            .iter()
+            .map(|&direction| DirectionStats {
+                direction,
+                score_survive: 0.0,
+                score_win: 0.0,
+                playouts: 0,
+            })
+            .collect();
+
+        let mut rngs_by_direction: Vec<_> = stats_by_direction
+            .iter()
+            .map(|_| child_rng(&mut self.rng))
+            .collect();
+        let mut heatmaps_by_direction: Vec<_> = stats_by_direction
+            .iter()
+            .map(|_| Heatmap::new(board.board_size()))
+            .collect();
+        let playouts_per_round = self.playouts_per_round(board, &no_crash_directions);
+        debug!("playouts per round: {:?}", playouts_per_round);
+
+        if let Some(territory_cache) = self.territory_cache.as_mut() {
+            territory_cache.get_mut().unwrap().clear();
+        }
+
+        let (width, height) = board.board_size();
+        let free_fraction = board.free_cells().count() as f64 / (width * height) as f64;
+        let max_steps = playout::depth_for_free_fraction(self.max_steps, free_fraction);
+
+        let run_one_playout = |board: &mut BoardTracker,
+                               rng: &mut StdRng,
+                               stats: &mut DirectionStats,
+                               heatmap: &mut Heatmap| {
+            let clear_on_death = rng.gen::<f32>() < self.clear_on_death_prob;
+            let options = playout::PlayoutOptions {
+                measure_territory: self.territory_weight > 0.0,
+                stop_on_territory_majority: self.stop_on_territory_majority,
+                stop_on_separation: self.stop_on_separation,
+                record_own_cells: self.record_heatmap,
+                ..playout::PlayoutOptions::new(max_steps, clear_on_death)
+            };
+            let playout_result = run_random_playout(
+                board,
+                rng,
+                self.player_id,
+                stats.direction,
+                options,
+                self.territory_cache.as_ref(),
+                self.opponent_straight_prob,
+            );
+
+            heatmap.add(&playout_result.own_cells);
+            stats.score_survive += playout_result.survived_steps as f64;
+            stats.score_win += playout_win_score(&playout_result, self.territory_weight);
+            stats.playouts += 1;
+        };
+
+        // Every direction gets its playouts each round, so that no direction is
+        // starved when there are fewer threads than directions
+        #[cfg(feature = "parallel")]
+        {
+            let mut playout_boards: Vec<_> =
+                stats_by_direction.iter().map(|_| board.clone()).collect();
+            while step_start.elapsed() <= time_budget {
+                stats_by_direction
+                    .par_iter_mut()
+                    .zip(rngs_by_direction.par_iter_mut())
+                    .zip(playout_boards.par_iter_mut())
+                    .zip(heatmaps_by_direction.par_iter_mut())
+                    .zip(playouts_per_round.par_iter())
+                    .for_each(|((((stats, rng), playout_board), heatmap), &n_playouts)| {
+                        for _ in 0..n_playouts {
+                            run_one_playout(playout_board, rng, stats, heatmap);
+                        }
+                    });
+            }
+        }
+
+        #[cfg(not(feature = "parallel"))]
+        {
+            let mut playout_board = board.clone();
+            let rng = &mut rngs_by_direction[0];
+            let schedule: Vec<usize> = playouts_per_round
+                .iter()
+                .enumerate()
+                .flat_map(|(i_direction, &n_playouts)| std::iter::repeat_n(i_direction, n_playouts))
+                .collect();
+            for i_playout in 0.. {
+                if step_start.elapsed() > time_budget {
+                    break;
+                }
+                let i_direction = schedule[i_playout % schedule.len()];
+                run_one_playout(
+                    &mut playout_board,
+                    rng,
+                    &mut stats_by_direction[i_direction],
+                    &mut heatmaps_by_direction[i_direction],
+                );
+            }
+        }
+
+        for stats in stats_by_direction.iter_mut() {
+            if stats.playouts > 0 {
+                stats.score_survive /= stats.playouts as f64;
+                stats.score_win /= stats.playouts as f64;
+            }
+            debug!("{:?}", stats);
+        }
+        if let Some(territory_cache) = self.territory_cache.as_mut() {
+            let territory_cache = territory_cache.get_mut().unwrap();
+            debug!(
+                "territory cache: {} hits, {} misses",
+                territory_cache.hits, territory_cache.misses
+            );
+        }
+
+        let heatmap = self.record_heatmap.then(|| {
+            let mut heatmap = Heatmap::new(board.board_size());
+            for direction_heatmap in &heatmaps_by_direction {
+                heatmap.merge(direction_heatmap);
+            }
+            debug!("playout heatmap:\n{}", heatmap.render_ascii());
+            heatmap
+        });
+        self.last_trace = Some(StepTrace {
+            playouts: stats_by_direction.iter().map(|stats| stats.playouts).sum(),
+            direction_scores: stats_by_direction
+                .iter()
+                .map(|stats| DirectionScore {
+                    direction: stats.direction,
+                    mean_score: match self.mode {
+                        PlayoutAfterNextStrategyMode::SurvivedSteps => stats.score_survive,
+                        _ => stats.score_win,
+                    },
+                    playouts: stats.playouts,
+                })
+                .collect(),
+            heatmap,
+            ..Default::default()
+        });
+
+        let win_scores = |stats: &[&DirectionStats]| -> Vec<(Direction, f64)> {
+            stats.iter().map(|s| (s.direction, s.score_win)).collect()
+        };
+        let survive_scores = |stats: &[&DirectionStats]| -> Vec<(Direction, f64)> {
+            stats
+                .iter()
+                .map(|s| (s.direction, s.score_survive))
+                .collect()
+        };
+        let all_stats: Vec<_> = stats_by_direction.iter().collect();
+        let scores = match self.mode {
+            PlayoutAfterNextStrategyMode::SurviveMoreThanWin => {
+                // Shallow playouts can't survive longer than their depth
+                let good_enough_survive_thresh = 20.0_f64.min(max_steps as f64);
+                let good_enough_survive_stats: Vec<_> = stats_by_direction
+                    .iter()
+                    .filter(|stats| stats.score_survive >= good_enough_survive_thresh)
+                    .collect();
+                if !good_enough_survive_stats.is_empty() {
+                    win_scores(&good_enough_survive_stats)
+                } else {
+                    // HACK not mean but we uniform sample anyway
+                    survive_scores(&all_stats)
+                }
+            }
+            PlayoutAfterNextStrategyMode::WinProbability => win_scores(&all_stats),
+            PlayoutAfterNextStrategyMode::SurvivedSteps => survive_scores(&all_stats),
+        };
+        best_direction_with_space_tiebreak(board, self.player_id, &scores)
+    }
+}
+
+// Scores within this fraction of the best score count as tied
+const NEAR_TIE_TOLERANCE: f64 = 0.01;
+
+// Picks the highest scoring direction. With few playouts several directions
+// often end up (nearly) tied, in which case we prefer the one that leaves us
+// the most reachable space right after the move.
+fn best_direction_with_space_tiebreak(
+    board: &BoardTracker,
+    player_id: usize,
+    scores: &[(Direction, f64)],
+) -> Direction {
+    let best_score = scores
+        .iter()
+        .map(|&(_, score)| score)
+        .max_by(|a, b| a.partial_cmp(b).unwrap())
+        .unwrap();
+    let tie_thresh = best_score - best_score.abs().max(1.0) * NEAR_TIE_TOLERANCE;
+    let tied: Vec<Direction> = scores
+        .iter()
+        .filter(|&&(_, score)| score >= tie_thresh)
+        .map(|&(direction, _)| direction)
+        .collect();
+    if tied.len() == 1 {
+        return tied[0];
+    }
+
+    let head = board.get_player_latest_pos(player_id).unwrap();
+    let reachable =
+        reachability::reachable_after_each_move(board.board_size(), board.topology(), board, head);
+    let reachable_for = |direction: Direction| {
+        let i = Direction::all_directions()
+            .iter()
+            .position(|&d| d == direction)
+            .unwrap();
+        reachable[i].unwrap_or(0)
+    };
+    tied.into_iter()
+        .max_by_key(|&direction| reachable_for(direction))
+        .unwrap()
+}
+
+struct UcbPlayoutStrategy {
+    player_id: usize,
+    max_steps: usize,
+    exploration: f64,
+    clear_on_death_prob: f32,
+    opponent_straight_prob: f64,
+    rng: StdRng,
+    last_trace: Option<StepTrace>,
+}
+
+impl UcbPlayoutStrategy {
+    fn new(max_steps: usize, exploration: f64, clear_on_death_prob: f32, rng: StdRng) -> Self {
+        assert!(max_steps > 0);
+        assert!(exploration >= 0.0);
+        assert!((0.0..=1.0).contains(&clear_on_death_prob));
+        Self {
+            player_id: 0,
+            max_steps,
+            exploration,
+            clear_on_death_prob,
+            opponent_straight_prob: 0.0,
+            rng,
+            last_trace: None,
+        }
+    }
+
+    fn with_opponent_straight_prob(mut self, opponent_straight_prob: f64) -> Self {
+        assert!((0.0..=1.0).contains(&opponent_straight_prob));
+        self.opponent_straight_prob = opponent_straight_prob;
+        self
+    }
+}
+
+impl Strategy for UcbPlayoutStrategy {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+    }
+
+    fn take_trace(&mut self) -> Option<StepTrace> {
+        self.last_trace.take()
+    }
+
+    fn step_detailed(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> StepDecision {
+        let direction = self.step_in_context(board, time_budget, context);
+        StepDecision::from_trace(direction, self.last_trace.as_ref())
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        let step_start = Instant::now();
+        self.last_trace = None;
+
+        let n_players = board.count_seen();
+        assert!(n_players > 0);
+        assert!(self.player_id < n_players);
+
+        let mut no_crash_directions = board.safe_moves(self.player_id);
+        no_crash_directions.shuffle(&mut self.rng);
+
+        if no_crash_directions.is_empty() {
+            return MoveChoice::safe_or_forced_crash(board, self.player_id, None)
+                .logged_direction();
+        }
+        if no_crash_directions.len() == 1 {
+            return no_crash_directions[0];
+        }
+
+        #[derive(Clone, Debug)]
+        struct DirectionStats {
+            direction: Direction,
+            score_win: f64,
+            playouts: usize,
+        }
+        let mut stats_by_direction: Vec<_> = no_crash_directions
+            .iter()
+            .map(|&direction| DirectionStats {
+                direction,
+                score_win: 0.0,
+                playouts: 0,
+            })
+            .collect();
+
+        let mut playout_board = board.clone();
+        for total_playouts in 0.. {
+            if step_start.elapsed() > time_budget {
+                break;
+            }
+
+            // Every arm gets one playout before UCB values are compared
+            let i_arm = match stats_by_direction.iter().position(|s| s.playouts == 0) {
+                Some(i) => i,
+                None => {
+                    let ln_total = (total_playouts as f64).ln();
+                    let ucb = |s: &DirectionStats| {
+                        s.score_win / s.playouts as f64
+                            + self.exploration * (ln_total / s.playouts as f64).sqrt()
+                    };
+                    (0..stats_by_direction.len())
+                        .max_by(|&a, &b| {
+                            ucb(&stats_by_direction[a])
+                                .partial_cmp(&ucb(&stats_by_direction[b]))
+                                .unwrap()
+                        })
+                        .unwrap()
+                }
+            };
+
+            let clear_on_death = self.rng.gen::<f32>() < self.clear_on_death_prob;
+            let playout_result = run_random_playout(
+                &mut playout_board,
+                &mut self.rng,
+                self.player_id,
+                stats_by_direction[i_arm].direction,
+                playout::PlayoutOptions::new(self.max_steps, clear_on_death),
+                None,
+                self.opponent_straight_prob,
+            );
+
+            let stats = &mut stats_by_direction[i_arm];
+            stats.score_win += playout_win_score(&playout_result, 0.0);
+            stats.playouts += 1;
+        }
+
+        for stats in stats_by_direction.iter_mut() {
+            if stats.playouts > 0 {
+                stats.score_win /= stats.playouts as f64;
+            }
+            debug!("{:?}", stats);
+        }
+
+        self.last_trace = Some(StepTrace {
+            playouts: stats_by_direction.iter().map(|stats| stats.playouts).sum(),
+            direction_scores: stats_by_direction
+                .iter()
+                .map(|stats| DirectionScore {
+                    direction: stats.direction,
+                    mean_score: stats.score_win,
+                    playouts: stats.playouts,
+                })
+                .collect(),
+            ..Default::default()
+        });
+
+        stats_by_direction
+            .iter()
+            .max_by(|a, b| a.score_win.partial_cmp(&b.score_win).unwrap())
+            .unwrap()
+            .direction
+    }
+}
+
+#[derive(Clone)]
+struct EndgameState {
+    occupied_mask: Vec<bool>,
+    own_pos: (usize, usize),
+    other_pos: (usize, usize),
+}
+
+enum EndgameOutcome {
+    Continue(EndgameState),
+    Win,
+    Lose,
+    Draw,
+}
+
+impl EndgameState {
+    fn apply(
+        &self,
+        size: (usize, usize),
+        own_direction: Direction,
+        other_direction: Direction,
+    ) -> EndgameOutcome {
+        let (width, _height) = size;
+
+        // Same collision rules as playout::run_playout
+        let own_new_pos = own_direction.offset_pos(self.own_pos, size);
+        let other_new_pos = other_direction.offset_pos(self.other_pos, size);
+        let head_on = own_new_pos == other_new_pos;
+        let own_dies = head_on || self.occupied_mask[own_new_pos.1 * width + own_new_pos.0];
+        let other_dies = head_on || self.occupied_mask[other_new_pos.1 * width + other_new_pos.0];
+
+        match (own_dies, other_dies) {
+            (true, true) => EndgameOutcome::Draw,
+            (true, false) => EndgameOutcome::Lose,
+            (false, true) => EndgameOutcome::Win,
+            (false, false) => {
+                let mut occupied_mask = self.occupied_mask.clone();
+                occupied_mask[own_new_pos.1 * width + own_new_pos.0] = true;
+                occupied_mask[other_new_pos.1 * width + other_new_pos.0] = true;
+                EndgameOutcome::Continue(EndgameState {
+                    occupied_mask,
+                    own_pos: own_new_pos,
+                    other_pos: other_new_pos,
+                })
+            }
+        }
+    }
+
+    fn evaluate(&self, size: (usize, usize)) -> f64 {
+        let (width, height) = size;
+        // The endgame search only models the wrapping GPN board
+        let owners = voronoi::voronoi_control(
+            size,
+            Topology::Torus,
+            &self.occupied_mask,
+            &[self.own_pos, self.other_pos],
+        );
+        let own_cells = voronoi::count_controlled(&owners, 0);
+        let other_cells = voronoi::count_controlled(&owners, 1);
+        (own_cells as f64 - other_cells as f64) / (width * height) as f64
+    }
+}
+
+struct MinimaxEndgameStrategy<T: Strategy> {
+    player_id: usize,
+    max_depth: usize,
+    fallback_strategy: T,
+}
+
+impl<T: Strategy> MinimaxEndgameStrategy<T> {
+    const WIN_SCORE: f64 = 1000.0;
+    const DRAW_SCORE: f64 = -500.0;
+    const LOSE_SCORE: f64 = -1000.0;
+
+    // Roughly 3 non-suicidal moves per player, with some alpha-beta pruning
+    const EFFECTIVE_BRANCHING: f64 = 6.0;
+
+    fn new(max_depth: usize, fallback_strategy: T) -> Self {
+        assert!(max_depth > 0);
+        Self {
+            player_id: 0,
+            max_depth,
+            fallback_strategy,
+        }
+    }
+
+    fn search(
+        state: &EndgameState,
+        size: (usize, usize),
+        depth: usize,
+        alpha: f64,
+        beta: f64,
+    ) -> (f64, Direction) {
+        Self::search_ordered(
+            state,
+            size,
+            depth,
+            alpha,
+            beta,
+            Direction::all_directions(),
+            None,
+        )
+        .unwrap()
+    }
+
+    // Tries our moves in the given order. None if the deadline passed before
+    // all of them were searched, which is only checked between our moves.
+    fn search_ordered(
+        state: &EndgameState,
+        size: (usize, usize),
+        depth: usize,
+        mut alpha: f64,
+        beta: f64,
+        own_directions: [Direction; 4],
+        deadline: Option<Instant>,
+    ) -> Option<(f64, Direction)> {
+        if depth == 0 {
+            return Some((state.evaluate(size), Direction::Down));
+        }
+
+        let mut best: Option<(f64, Direction)> = None;
+        for own_direction in own_directions {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return None;
+            }
+            let mut worst = f64::INFINITY;
+            for other_direction in Direction::all_directions() {
+                let value = match state.apply(size, own_direction, other_direction) {
+                    EndgameOutcome::Win => Self::WIN_SCORE,
+                    EndgameOutcome::Lose => Self::LOSE_SCORE,
+                    EndgameOutcome::Draw => Self::DRAW_SCORE,
+                    EndgameOutcome::Continue(child) => {
+                        Self::search(&child, size, depth - 1, alpha, worst.min(beta)).0
+                    }
+                };
+                worst = worst.min(value);
+                if worst <= alpha {
+                    break;
+                }
+            }
+
+            if best.is_none() || worst > best.unwrap().0 {
+                best = Some((worst, own_direction));
+            }
+            alpha = alpha.max(worst);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    // Searches depth 1, 2, ... until the budget runs out, trying the previous
+    // depth's best move first. Returns the result of the deepest search that
+    // finished, along with its depth. Depth 1 always finishes.
+    fn iterative_deepening(
+        &self,
+        state: &EndgameState,
+        size: (usize, usize),
+        time_budget: Duration,
+    ) -> (f64, Direction, usize) {
+        let deadline = Instant::now() + time_budget;
+        let mut iteration_start = Instant::now();
+        let (mut score, mut direction) =
+            Self::search(state, size, 1, f64::NEG_INFINITY, f64::INFINITY);
+        let mut depth = 1;
+        while depth < self.max_depth {
+            // The deadline is only checked between our top-level moves, so
+            // don't start a depth that likely can't finish in time
+            let predicted_duration = iteration_start.elapsed().mul_f64(Self::EFFECTIVE_BRANCHING);
+            if Instant::now() + predicted_duration > deadline {
+                break;
+            }
+            iteration_start = Instant::now();
+            let mut own_directions = Direction::all_directions();
+            let i_best = own_directions.iter().position(|&d| d == direction).unwrap();
+            own_directions[..=i_best].rotate_right(1);
+
+            match Self::search_ordered(
+                state,
+                size,
+                depth + 1,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                own_directions,
+                Some(deadline),
+            ) {
+                Some((new_score, new_direction)) => {
+                    score = new_score;
+                    direction = new_direction;
+                    depth += 1;
+                }
+                None => break,
+            }
+        }
+        (score, direction, depth)
+    }
+
+    // Our and the other player's position, if exactly the two of us are left
+    fn endgame_positions(&self, board: &BoardTracker) -> Option<((usize, usize), (usize, usize))> {
+        // The search only models the wrapping GPN board
+        if board.topology() != Topology::Torus
+            || board.count_alive() != 2
+            || board.is_dead(self.player_id)
+        {
+            return None;
+        }
+        let other_player_id = (0..board.count_seen())
+            .find(|&player_id| player_id != self.player_id && !board.is_dead(player_id))
+            .unwrap();
+        Some((
+            board.get_player_latest_pos(self.player_id)?,
+            board.get_player_latest_pos(other_player_id)?,
+        ))
+    }
+}
+
+impl<T: Strategy> Strategy for MinimaxEndgameStrategy<T> {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.player_id = game_info.player_id as usize;
+        self.fallback_strategy.start(game_info);
+    }
+
+    fn take_trace(&mut self) -> Option<StepTrace> {
+        self.fallback_strategy.take_trace()
+    }
+
+    fn step_in_context(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> Direction {
+        if self.endgame_positions(board).is_none() {
+            return self
+                .fallback_strategy
+                .step_in_context(board, time_budget, context);
+        }
+        self.step(board, time_budget)
+    }
+
+    fn step_detailed(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> StepDecision {
+        if self.endgame_positions(board).is_none() {
+            return self
+                .fallback_strategy
+                .step_detailed(board, time_budget, context);
+        }
+        StepDecision::without_playouts(self.step(board, time_budget))
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        let (own_pos, other_pos) = match self.endgame_positions(board) {
+            Some(positions) => positions,
+            None => return self.fallback_strategy.step(board, time_budget),
+        };
+
+        let size = board.board_size();
+        let state = EndgameState {
+            occupied_mask: board.occupied_mask(),
+            own_pos,
+            other_pos,
+        };
+
+        let (score, direction, depth) = self.iterative_deepening(&state, size, time_budget);
+        debug!("minimax depth {} score {}", depth, score);
+        direction
+    }
+}
+
+struct SeparateEarlyLateStrategy<A: Strategy, B: Strategy> {
+    early_strategy: A,
+    late_strategy: B,
+    switch_alive_ratio: f64,
+}
+
+impl<A: Strategy, B: Strategy> SeparateEarlyLateStrategy<A, B> {
+    fn new(early_strategy: A, late_strategy: B, switch_alive_ratio: f64) -> Self {
+        assert!((0.0..=1.0).contains(&switch_alive_ratio));
+        Self {
+            early_strategy,
+            late_strategy,
+            switch_alive_ratio,
+        }
+    }
+
+    fn active_strategy(&mut self, board: &BoardTracker) -> &mut dyn Strategy {
+        let n_players = board.count_positioned();
+        assert!(n_players > 0);
+
+        let alive_ratio = board.count_alive() as f64 / n_players as f64;
+        if alive_ratio < self.switch_alive_ratio {
+            &mut self.late_strategy
+        } else {
+            &mut self.early_strategy
+        }
+    }
+}
+
+impl<A: Strategy, B: Strategy> Strategy for SeparateEarlyLateStrategy<A, B> {
+    fn start(&mut self, game_info: &GameInfo) {
+        self.early_strategy.start(game_info);
+        self.late_strategy.start(game_info);
+    }
+
+    fn take_trace(&mut self) -> Option<StepTrace> {
+        let early_trace = self.early_strategy.take_trace();
+        let late_trace = self.late_strategy.take_trace();
+        late_trace.or(early_trace)
+    }
+
+    fn step_in_context(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> Direction {
+        self.active_strategy(board)
+            .step_in_context(board, time_budget, context)
+    }
+
+    fn step_detailed(
+        &mut self,
+        board: &BoardTracker,
+        time_budget: Duration,
+        context: &StepContext,
+    ) -> StepDecision {
+        self.active_strategy(board)
+            .step_detailed(board, time_budget, context)
+    }
+
+    fn step(&mut self, board: &BoardTracker, time_budget: Duration) -> Direction {
+        self.active_strategy(board).step(board, time_budget)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoundOutcome {
+    Win,
+    Lose,
+    Interrupted,
+}
+
+#[derive(Debug, Default)]
+struct RoundStats {
+    rounds: usize,
+    wins: usize,
+    losses: usize,
+}
+
+impl RoundStats {
+    fn record(&mut self, outcome: RoundOutcome) {
+        match outcome {
+            RoundOutcome::Win => self.wins += 1,
+            RoundOutcome::Lose => self.losses += 1,
+            RoundOutcome::Interrupted => return,
+        }
+        self.rounds += 1;
+    }
+
+    fn win_rate(&self) -> f64 {
+        if self.rounds == 0 {
+            0.0
+        } else {
+            self.wins as f64 / self.rounds as f64
+        }
+    }
+}
+
+impl std::fmt::Display for RoundStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} rounds, {} wins, {} losses ({:.1}% win rate)",
+            self.rounds,
+            self.wins,
+            self.losses,
+            self.win_rate() * 100.0
+        )
+    }
+}
+
+#[derive(Clone)]
+struct Taunter {
+    taunts: Vec<String>,
+    min_interval: Duration,
+    last_sent_at: Option<Instant>,
+    next_taunt: usize,
+}
+
+impl Taunter {
+    fn new(taunts: Vec<String>, min_interval: Duration) -> Self {
+        assert!(!taunts.is_empty());
+        Self {
+            taunts,
+            min_interval,
+            last_sent_at: None,
+            next_taunt: 0,
+        }
+    }
+
+    fn from_env() -> Result<Option<Self>> {
+        let taunts = match std::env::var("GPN_SNAKE_TAUNTS") {
+            Ok(taunts) => parse_taunts(&taunts)?,
+            Err(_) => return Ok(None),
+        };
+        if taunts.is_empty() {
+            return Ok(None);
+        }
+        let min_interval = match std::env::var("GPN_SNAKE_TAUNT_INTERVAL_MS") {
+            Ok(ms) => Duration::from_millis(ms.parse()?),
+            Err(_) => Duration::from_secs(5),
+        };
+        Ok(Some(Self::new(taunts, min_interval)))
+    }
+
+    fn on_death(&mut self, own_player_id: i32, dead_player_ids: &[i32]) -> Option<String> {
+        if !dead_player_ids.iter().any(|&id| id != own_player_id) {
+            return None;
+        }
+        if let Some(last_sent_at) = self.last_sent_at {
+            if last_sent_at.elapsed() < self.min_interval {
+                return None;
+            }
+        }
+
+        self.last_sent_at = Some(Instant::now());
+        let taunt = self.taunts[self.next_taunt].clone();
+        self.next_taunt = (self.next_taunt + 1) % self.taunts.len();
+        Some(taunt)
+    }
+}
+
+fn parse_taunts(taunts: &str) -> Result<Vec<String>> {
+    taunts
+        .split(';')
+        .map(|taunt| taunt.trim())
+        .filter(|taunt| !taunt.is_empty())
+        .map(|taunt| {
+            check_field("taunt", taunt)?;
+            Ok(taunt.to_string())
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct RoundConfig {
+    budget_fraction: f64,
+    replay_dir: Option<PathBuf>,
+    metrics_path: Option<PathBuf>,
+    read_timeout: Duration,
+    clear_dead_trails: bool,
+    desync_threshold: usize,
+    // Compute and log moves without sending them, to shadow a round
+    dry_run: bool,
+    // Send one fallback move if the server rejects ours instead of leaving the round
+    retry_rejected_moves: bool,
+    room: Option<String>,
+    // The account playing, set per connection. Keeps replays and metrics of
+    // several accounts apart.
+    username: String,
+}
+
+impl RoundConfig {
+    fn from_env() -> Result<Self> {
+        let budget_fraction = match std::env::var("GPN_SNAKE_BUDGET_FRACTION") {
+            Ok(value) => value.parse()?,
+            Err(_) => 1.0 / 3.0,
+        };
+        if !(0.0..=1.0).contains(&budget_fraction) {
+            return Err(anyhow!("budget_fraction must be between 0 and 1"));
+        }
+        let read_timeout = match std::env::var("GPN_SNAKE_READ_TIMEOUT_MS") {
+            Ok(ms) => Duration::from_millis(ms.parse()?),
+            Err(_) => Duration::from_secs(60),
+        };
+        if read_timeout.is_zero() {
+            return Err(anyhow!("read_timeout must be positive"));
+        }
+        Ok(Self {
+            budget_fraction,
+            replay_dir: std::env::var_os("GPN_SNAKE_REPLAY_DIR").map(PathBuf::from),
+            metrics_path: std::env::var_os("GPN_SNAKE_METRICS").map(PathBuf::from),
+            read_timeout,
+            clear_dead_trails: match std::env::var("GPN_SNAKE_CLEAR_DEAD_TRAILS") {
+                Ok(value) => value.parse()?,
+                Err(_) => true,
+            },
+            desync_threshold: match std::env::var("GPN_SNAKE_DESYNC_THRESHOLD") {
+                Ok(value) => value.parse()?,
+                Err(_) => 3,
+            },
+            dry_run: false,
+            retry_rejected_moves: match std::env::var("GPN_SNAKE_RETRY_REJECTED_MOVES") {
+                Ok(value) => value.parse()?,
+                Err(_) => true,
+            },
+            room: std::env::var("GPN_SNAKE_ROOM").ok(),
+            username: String::new(),
+        })
+    }
+}
+
+// Crashing players get a pos onto a taken cell right before their die, so
+// only conflicts that no die explains are fed in. Those should never happen
+// if our board matches the server's, so once too many pile up in a round we
+// stop trusting the board.
+struct DesyncDetector {
+    threshold: usize,
+    conflicts: usize,
+}
+
+impl DesyncDetector {
+    fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            conflicts: 0,
+        }
+    }
+
+    // Returns true the first time the threshold is crossed
+    fn record_conflicts(&mut self, count: usize) -> bool {
+        let was_desynced = self.desynced();
+        self.conflicts += count;
+        !was_desynced && self.desynced()
+    }
+
+    fn desynced(&self) -> bool {
+        self.conflicts > self.threshold
+    }
+}
+
+struct TickBudget {
+    budget_fraction: f64,
+    intervals: std::collections::VecDeque<Duration>,
+}
+
+impl TickBudget {
+    const WINDOW: usize = 9;
+    const SAFETY_MARGIN: Duration = Duration::from_millis(10);
+    const DEFAULT_BUDGET: Duration = Duration::from_millis(50);
+    const MIN_BUDGET: Duration = Duration::from_millis(1);
+    const MAX_BUDGET: Duration = Duration::from_secs(2);
+
+    fn new(budget_fraction: f64) -> Self {
+        Self {
+            budget_fraction,
+            intervals: std::collections::VecDeque::with_capacity(Self::WINDOW),
+        }
+    }
+
+    fn record_interval(&mut self, interval: Duration) {
+        if self.intervals.len() == Self::WINDOW {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(interval);
+    }
+
+    fn median_interval(&self) -> Option<Duration> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<_> = self.intervals.iter().cloned().collect();
+        sorted.sort();
+        let mid = sorted.len() / 2;
+        if sorted.len() % 2 == 0 {
+            Some((sorted[mid - 1] + sorted[mid]) / 2)
+        } else {
+            Some(sorted[mid])
+        }
+    }
+
+    // Before any real tick interval is known (the first tick) this returns a
+    // conservative default instead of guessing from the join-to-game gap
+    fn budget(&self) -> Duration {
+        match self.median_interval() {
+            Some(interval) => interval
+                .mul_f64(self.budget_fraction)
+                .saturating_sub(Self::SAFETY_MARGIN)
+                .clamp(Self::MIN_BUDGET, Self::MAX_BUDGET),
+            None => Self::DEFAULT_BUDGET,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PosConflict {
+    pos: (usize, usize),
+    previous_player_id: usize,
+    player_id: usize,
+}
+
+// The move we sent this tick, so that an error can be tied back to it
+struct PendingMove {
+    direction: Direction,
+    retried: bool,
+}
+
+// The server doesn't say which message an error is about, so this guesses
+// from the text
+fn is_move_error(message: &str) -> bool {
+    message.to_lowercase().contains("move")
+}
+
+// clear_dead_trails removes the trails of dead players, like the server does
+fn update_board(
+    board: &mut BoardTracker,
+    msg: &ServerMessage,
+    clear_dead_trails: bool,
+) -> Result<Option<PosConflict>> {
+    match msg {
+        &ServerMessage::Pos { player_id, x, y } => {
+            let (width, height) = board.board_size();
+            let player_id = usize::try_from(player_id)
+                .ok()
+                .filter(|&player_id| player_id <= BoardTracker::MAX_PLAYER_ID)
+                .ok_or_else(|| anyhow!("invalid player id in pos: {}", player_id))?;
+            let pos = match (usize::try_from(x), usize::try_from(y)) {
+                (Ok(x), Ok(y)) if x < width && y < height => (x, y),
+                _ => {
+                    return Err(anyhow!(
+                        "pos ({}, {}) out of bounds for {}x{} board",
+                        x,
+                        y,
+                        width,
+                        height
+                    ))
+                }
+            };
+            let previous_player_id = board.get_cell_player(pos);
+            if board.record_pos(player_id, pos) {
+                let conflict = PosConflict {
+                    pos,
+                    previous_player_id: previous_player_id.unwrap(),
+                    player_id,
+                };
+                debug!("pos conflict {:?}", conflict);
+                return Ok(Some(conflict));
+            }
+        }
+        ServerMessage::Die { player_ids } => {
+            for &player_id in player_ids {
+                // Dying creates an entry for every id up to this one
+                let player_id = usize::try_from(player_id)
+                    .ok()
+                    .filter(|&player_id| player_id <= BoardTracker::MAX_PLAYER_ID)
+                    .ok_or_else(|| anyhow!("invalid player id in die: {}", player_id))?;
+                info!(
+                    "player {} ({}) died",
+                    player_id,
+                    board.player_name(player_id).unwrap_or("unknown")
+                );
+                board.record_death(player_id, clear_dead_trails);
+            }
+        }
+        ServerMessage::Player { player_id, name } => {
+            let player_id = (*player_id)
+                .try_into()
+                .map_err(|_| anyhow!("invalid player id in player: {}", player_id))?;
+            board.set_player_name(player_id, name.clone());
+        }
+        _ => (),
+    }
+    Ok(None)
+}
+
+const POSTMORTEM_MOVES: usize = 8;
+
+// What the board looked like when we died and how we got there, oldest move first
+fn postmortem(
+    board: &BoardTracker,
+    recent_directions: &std::collections::VecDeque<Direction>,
+) -> String {
+    let moves: Vec<String> = recent_directions
+        .iter()
+        .map(|direction| direction.to_string())
+        .collect();
+    format!(
+        "last moves: {}\n{}",
+        moves.join(" "),
+        board.render_ascii_with_heads()
+    )
+}
+
+fn run_round<S: Strategy, R: Read, W: Write>(
+    mut strategy: S,
+    round_config: &RoundConfig,
+    mut taunter: Option<&mut Taunter>,
+    reader: &mut GameReader<R>,
+    writer: &mut GameWriter<W>,
+) -> Result<RoundOutcome> {
+    info!("waiting for next round");
+
+    let mut recorder = match &round_config.replay_dir {
+        Some(replay_dir) => {
+            let (recorder, path) =
+                replay::ReplayRecorder::create(replay_dir, &round_config.username)?;
+            info!("recording replay to {}", path.display());
+            Some(recorder)
+        }
+        None => None,
+    };
+    let mut metrics_writer = match &round_config.metrics_path {
+        Some(metrics_path) => Some(MetricsWriter::open(metrics_path)?),
+        None => None,
+    };
+
+    let mut server_info = ServerInfo::default();
+    let (mut board, mut own_player_id) = loop {
+        let msg = reader.read()?;
+        debug!("{:?}", msg);
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(&msg)?;
+        }
+
+        match msg {
+            ServerMessage::Game { message } => {
+                info!(
+                    "starting game {:?} on server version {}",
+                    message,
+                    server_info.version.as_deref().unwrap_or("unknown")
+                );
+                let board = message.new_board()?;
+                strategy.start_with_server_info(&message, &server_info);
+                break (board, message.player_id);
+            }
+            ServerMessage::Error { message } => {
+                return Err(anyhow!("error: {}", message));
+            }
+            ServerMessage::Motd { ref message } => server_info = ServerInfo::from_motd(message),
+            ServerMessage::Win { .. } => return Ok(RoundOutcome::Win),
+            ServerMessage::Lose { .. } => return Ok(RoundOutcome::Lose),
+            _ => (),
+        };
+
+        if shutdown::requested() {
+            info!("shutdown requested, not waiting for next round");
+            return Ok(RoundOutcome::Interrupted);
+        }
+    };
+
+    let mut tick_budget = TickBudget::new(round_config.budget_fraction);
+    let mut last_tick_at: Option<Instant> = None;
+    let mut pending_collisions: Vec<PosConflict> = Vec::new();
+    let mut desync_detector = DesyncDetector::new(round_config.desync_threshold);
+    let mut pending_move: Option<PendingMove> = None;
+    let mut recent_directions = std::collections::VecDeque::with_capacity(POSTMORTEM_MOVES);
+    let mut tick = 0;
+    let mut round_started_at = Instant::now();
+    let mut own_dead = false;
+    let mut queued = std::collections::VecDeque::new();
+
+    loop {
+        let msg = match queued.pop_front() {
+            Some(msg) => msg,
+            None => reader.read()?,
+        };
+        debug!("{:?}", msg);
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(&msg)?;
+        }
+
+        match msg {
+            ServerMessage::Tick => {
+                if shutdown::requested() {
+                    info!("shutdown requested, leaving round");
+                    return Ok(RoundOutcome::Interrupted);
+                }
+
+                // If we fell behind, act on the newest tick only, after applying
+                // everything that came before it
+                queued.extend(reader.read_all_available());
+                if queued.iter().any(|msg| matches!(msg, ServerMessage::Tick)) {
+                    debug!("skipping stale tick, {} messages buffered", queued.len());
+                    tick += 1;
+                    continue;
+                }
+
+                let tick_duration = last_tick_at.map(|last_tick_at| last_tick_at.elapsed());
+                last_tick_at = Some(Instant::now());
+                if let Some(tick_duration) = tick_duration {
+                    tick_budget.record_interval(tick_duration);
+                }
+                let time_budget = tick_budget.budget();
+                pending_move = None;
+                if !pending_collisions.is_empty() {
+                    warn!(
+                        "{} pos conflicts without a die since last tick: {:?}",
+                        pending_collisions.len(),
+                        pending_collisions
+                    );
+                    if desync_detector.record_conflicts(pending_collisions.len()) {
+                        warn!(
+                            "board looks desynced from the server after {} pos conflicts, playing conservatively for the rest of the round",
+                            desync_detector.conflicts
+                        );
+                    }
+                    pending_collisions.clear();
+                }
+                // The server ignores our moves once we're dead, so just wait for the round to end
+                if own_dead {
+                    writer.flush()?;
+                    continue;
+                }
+
+                let before_step = Instant::now();
+                let context = StepContext {
+                    tick,
+                    round_elapsed: round_started_at.elapsed(),
+                };
+                let decision = strategy.step_detailed(&board, time_budget, &context);
+                let mut direction = decision.direction;
+                if desync_detector.desynced() {
+                    // The server's board can only have more cells taken than ours
+                    // (missed pos), so avoid anything an opponent might reach too
+                    let own = own_player_id as usize;
+                    let conservative = board.safe_moves_conservative(own);
+                    if !conservative.is_empty() && !conservative.contains(&direction) {
+                        let scores: Vec<_> = conservative.iter().map(|&d| (d, 0.0)).collect();
+                        direction = best_direction_with_space_tiebreak(&board, own, &scores);
+                        info!(
+                            "overriding {} with conservative {} due to desync",
+                            decision.direction, direction
+                        );
+                    }
+                }
+                let step_duration = before_step.elapsed();
+                info!(
+                    "--- moving {} (confidence {:.3} from {} playouts, {} ms calc, {} ms budget, {} ms since last tick) ---",
+                    direction,
+                    decision.confidence,
+                    decision.playouts,
+                    step_duration.as_millis(),
+                    time_budget.as_millis(),
+                    tick_duration.map_or(0, |tick_duration| tick_duration.as_millis())
+                );
+                if round_config.dry_run {
+                    writer.flush()?;
+                } else {
+                    writer.write(&ClientMessage::Move { direction })?;
+                    pending_move = Some(PendingMove {
+                        direction,
+                        retried: false,
+                    });
+                }
+
+                if recent_directions.len() == POSTMORTEM_MOVES {
+                    recent_directions.pop_front();
+                }
+                recent_directions.push_back(direction);
+
+                let trace = strategy.take_trace();
+                if let Some(metrics_writer) = metrics_writer.as_mut() {
+                    metrics_writer.write(&StepTrace {
+                        username: round_config.username.clone(),
+                        tick,
+                        direction: Some(direction),
+                        calc_ms: step_duration.as_millis(),
+                        budget_ms: time_budget.as_millis(),
+                        ..trace.unwrap_or_default()
+                    })?;
+                }
+                tick += 1;
+            }
+            // We might have missed the win/lose of the previous round
+            ServerMessage::Game { message } => {
+                warn!("new game started mid-round, resetting board");
+                board = message.new_board()?;
+                strategy.start_with_server_info(&message, &server_info);
+                own_player_id = message.player_id;
+                last_tick_at = None;
+                pending_collisions.clear();
+                desync_detector = DesyncDetector::new(round_config.desync_threshold);
+                pending_move = None;
+                recent_directions.clear();
+                tick = 0;
+                round_started_at = Instant::now();
+                own_dead = false;
+            }
+            ServerMessage::Motd { ref message } => server_info = ServerInfo::from_motd(message),
+            ServerMessage::Error { message } => {
+                let pending = match pending_move.as_mut() {
+                    Some(pending)
+                        if round_config.retry_rejected_moves
+                            && !pending.retried
+                            && is_move_error(&message) =>
+                    {
+                        pending
+                    }
+                    _ => return Err(anyhow!("error: {}", message)),
+                };
+                let own = own_player_id as usize;
+                let scores: Vec<_> = board
+                    .safe_moves(own)
+                    .into_iter()
+                    .filter(|&direction| direction != pending.direction)
+                    .map(|direction| (direction, 0.0))
+                    .collect();
+                if scores.is_empty() {
+                    return Err(anyhow!(
+                        "error: {} (no fallback for rejected move {})",
+                        message,
+                        pending.direction
+                    ));
+                }
+                let direction = best_direction_with_space_tiebreak(&board, own, &scores);
+                warn!(
+                    "move {} rejected ({}), retrying with {}",
+                    pending.direction, message, direction
+                );
+                writer.write(&ClientMessage::Move { direction })?;
+                pending.direction = direction;
+                pending.retried = true;
+                // The postmortem should show the move the server took
+                if let Some(last_direction) = recent_directions.back_mut() {
+                    *last_direction = direction;
+                }
+            }
+            ServerMessage::Pos { .. } => {
+                if let Some(conflict) =
+                    update_board(&mut board, &msg, round_config.clear_dead_trails)?
+                {
+                    pending_collisions.push(conflict);
+                }
+            }
+            ServerMessage::Player { .. } => {
+                update_board(&mut board, &msg, round_config.clear_dead_trails)?;
+            }
+            ServerMessage::Die { ref player_ids } => {
+                // Moving onto a taken cell is how players crash
+                pending_collisions.retain(|conflict| {
+                    !player_ids
+                        .iter()
+                        .any(|&player_id| usize::try_from(player_id) == Ok(conflict.player_id))
+                });
+                if player_ids.contains(&own_player_id) {
+                    info!("we died, waiting for the round to end");
+                    info!("postmortem, {}", postmortem(&board, &recent_directions));
+                    own_dead = true;
+                }
+                if let Some(taunter) = taunter.as_mut() {
+                    // Sent together with our next move
+                    if let Some(message) = taunter.on_death(own_player_id, player_ids) {
+                        writer.write_no_flush(&ClientMessage::Chat { message })?;
+                    }
+                }
+                update_board(&mut board, &msg, round_config.clear_dead_trails)?;
+            }
+            ServerMessage::Message { .. } => (),
+            ServerMessage::Win { .. } => return Ok(RoundOutcome::Win),
+            ServerMessage::Lose { .. } => return Ok(RoundOutcome::Lose),
+        };
+    }
+}
+
+// Plays rounds on one connection for as long as the server keeps it open,
+// with a fresh strategy per round. Returns the last outcome once the server
+// closes the connection after at least one finished round.
+fn play_rounds<R: Read, W: Write>(
+    strategy_config: &StrategyConfig,
+    round_config: &RoundConfig,
+    mut taunter: Option<&mut Taunter>,
+    rng: &mut StdRng,
+    reader: &mut GameReader<R>,
+    writer: &mut GameWriter<W>,
+    mut on_round: impl FnMut(RoundOutcome),
+) -> Result<RoundOutcome> {
+    let mut last_outcome = None;
+    loop {
+        let strategy = strategy_config.build(rng);
+        match run_round(
+            strategy,
+            round_config,
+            taunter.as_deref_mut(),
+            reader,
+            writer,
+        ) {
+            Ok(RoundOutcome::Interrupted) => return Ok(RoundOutcome::Interrupted),
+            Ok(outcome) => {
+                on_round(outcome);
+                last_outcome = Some(outcome);
+            }
+            Err(err) => {
+                let closed = matches!(err.downcast_ref::<ReadError>(), Some(ReadError::Eof));
+                return match last_outcome {
+                    Some(outcome) if closed => Ok(outcome),
+                    _ => Err(err),
+                };
+            }
+        }
+    }
+}
+
+fn try_play(
+    host_port: &str,
+    (username, password): &(String, String),
+    strategy_config: &StrategyConfig,
+    round_config: &RoundConfig,
+    taunter: Option<&mut Taunter>,
+    rng: &mut StdRng,
+    on_round: impl FnMut(RoundOutcome),
+) -> Result<RoundOutcome> {
+    info!("connecting");
+
+    let stream = TcpStream::connect(host_port)?;
+    stream.set_read_timeout(Some(round_config.read_timeout))?;
+    let mut reader = GameReader::new(&stream);
+    let mut writer = GameWriter::new(&stream);
+
+    writer.write(&ClientMessage::Join {
+        username: username.clone(),
+        password: password.clone(),
+        room: round_config.room.clone(),
+    })?;
+
+    let outcome = play_rounds(
+        strategy_config,
+        round_config,
+        taunter,
+        rng,
+        &mut reader,
+        &mut writer,
+        on_round,
+    );
+    writer.flush().ok();
+    stream.shutdown(Shutdown::Both).ok();
+    outcome
+}
+
+fn rng_from_env() -> Result<StdRng> {
+    match std::env::var("GPN_SNAKE_SEED") {
+        Ok(seed) => Ok(StdRng::seed_from_u64(seed.parse()?)),
+        Err(_) => Ok(StdRng::from_entropy()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StrategyKind {
+    AlwaysDown,
+    Random,
+    Momentum,
+    GetAway,
+    SpaceFill,
+    WallHug,
+    Hunt,
+    Voronoi,
+    Playout,
+    Ucb,
+    Minimax,
+}
+
+impl std::str::FromStr for StrategyKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always-down" => Ok(StrategyKind::AlwaysDown),
+            "random" => Ok(StrategyKind::Random),
+            "momentum" => Ok(StrategyKind::Momentum),
+            "getaway" => Ok(StrategyKind::GetAway),
+            "spacefill" => Ok(StrategyKind::SpaceFill),
+            "wallhug" => Ok(StrategyKind::WallHug),
+            "hunt" => Ok(StrategyKind::Hunt),
+            "voronoi" => Ok(StrategyKind::Voronoi),
+            "playout" => Ok(StrategyKind::Playout),
+            "ucb" => Ok(StrategyKind::Ucb),
+            "minimax" => Ok(StrategyKind::Minimax),
+            _ => Err(anyhow!("unknown strategy: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StrategyConfig {
+    kind: StrategyKind,
+    max_steps: usize,
+    win_multiplier: usize,
+    territory_weight: f64,
+    stop_on_territory_majority: bool,
+    min_playout_budget: Duration,
+    stop_on_separation: bool,
+    heatmap: bool,
+    territory_cache: bool,
+    reachability_seeding: bool,
+    playout_opponent_straight_prob: f64,
+    getaway_open_space_weight: f64,
+    getaway_head_proximity_cost: usize,
+}
+
+impl StrategyConfig {
+    fn from_env() -> Result<Self> {
+        fn parse_env<T: std::str::FromStr>(name: &str, default: T) -> Result<T>
+        where
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            match std::env::var(name) {
+                Ok(value) => Ok(value.parse()?),
+                Err(_) => Ok(default),
+            }
+        }
+
+        let config = Self {
+            kind: std::env::var("GPN_SNAKE_STRATEGY")
+                .unwrap_or("playout".to_string())
+                .parse()?,
+            max_steps: parse_env("GPN_SNAKE_MAX_STEPS", 120)?,
+            win_multiplier: parse_env("GPN_SNAKE_WIN_MULTIPLIER", 1)?,
+            territory_weight: parse_env("GPN_SNAKE_TERRITORY_WEIGHT", 0.0)?,
+            stop_on_territory_majority: parse_env("GPN_SNAKE_STOP_ON_TERRITORY_MAJORITY", false)?,
+            min_playout_budget: Duration::from_millis(parse_env(
+                "GPN_SNAKE_MIN_PLAYOUT_BUDGET_MS",
+                5,
+            )?),
+            stop_on_separation: parse_env("GPN_SNAKE_STOP_ON_SEPARATION", false)?,
+            heatmap: parse_env("GPN_SNAKE_HEATMAP", false)?,
+            territory_cache: parse_env("GPN_SNAKE_TERRITORY_CACHE", false)?,
+            reachability_seeding: parse_env("GPN_SNAKE_REACHABILITY_SEEDING", false)?,
+            playout_opponent_straight_prob: parse_env(
+                "GPN_SNAKE_PLAYOUT_OPPONENT_STRAIGHT_PROB",
+                0.0,
+            )?,
+            getaway_open_space_weight: parse_env("GPN_SNAKE_GETAWAY_OPEN_SPACE_WEIGHT", 0.5)?,
+            getaway_head_proximity_cost: parse_env("GPN_SNAKE_GETAWAY_HEAD_PROXIMITY_COST", 0)?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.max_steps == 0 {
+            return Err(anyhow!("max_steps must be positive"));
+        }
+        if self.win_multiplier == 0 {
+            return Err(anyhow!("win_multiplier must be positive"));
+        }
+        if !(0.0..=1.0).contains(&self.territory_weight) {
+            return Err(anyhow!("territory_weight must be between 0 and 1"));
+        }
+        if !(0.0..=1.0).contains(&self.playout_opponent_straight_prob) {
+            return Err(anyhow!(
+                "playout_opponent_straight_prob must be between 0 and 1"
+            ));
+        }
+        if self.getaway_open_space_weight < 0.0 {
+            return Err(anyhow!("getaway_open_space_weight must not be negative"));
+        }
+        Ok(())
+    }
+
+    fn build(&self, rng: &mut StdRng) -> Box<dyn Strategy> {
+        let mut playout = || {
+            PlayoutAfterNextStrategy::new(
+                PlayoutAfterNextStrategyMode::WinProbability,
+                self.max_steps,
+                self.win_multiplier,
+                self.territory_weight,
+                self.stop_on_territory_majority,
+                0.5,
+                child_rng(rng),
+            )
+            .with_min_playout_budget(self.min_playout_budget)
+            .with_stop_on_separation(self.stop_on_separation)
+            .with_heatmap(self.heatmap)
+            .with_territory_cache(self.territory_cache)
+            .with_reachability_seeding(self.reachability_seeding)
+            .with_opponent_straight_prob(self.playout_opponent_straight_prob)
+        };
+        match self.kind {
+            StrategyKind::AlwaysDown => Box::new(AlwaysDownStrategy::new()),
+            StrategyKind::Random => Box::new(NoCrashRandomStrategy::new(child_rng(rng))),
+            StrategyKind::Momentum => Box::new(MomentumRandomStrategy::new(
+                0.8,
+                NoCrashRandomStrategy::new(child_rng(rng)),
+                child_rng(rng),
+            )),
+            StrategyKind::GetAway => Box::new(
+                GetAwayFromItAllStrategy::new(child_rng(rng))
+                    .with_open_space_weight(self.getaway_open_space_weight)
+                    .with_head_proximity_cost(self.getaway_head_proximity_cost),
+            ),
+            StrategyKind::SpaceFill => Box::new(SpaceFillStrategy::new()),
+            StrategyKind::WallHug => Box::new(WallHugStrategy::new()),
+            StrategyKind::Hunt => Box::new(HuntWeakestStrategy::new()),
+            StrategyKind::Voronoi => Box::new(VoronoiGreedyStrategy::new()),
+            StrategyKind::Playout => Box::new(playout()),
+            StrategyKind::Ucb => Box::new(
+                UcbPlayoutStrategy::new(self.max_steps, 1.0, 0.5, child_rng(rng))
+                    .with_opponent_straight_prob(self.playout_opponent_straight_prob),
+            ),
+            StrategyKind::Minimax => Box::new(MinimaxEndgameStrategy::new(8, playout())),
+        }
+    }
+}
+
+// A single game between the named strategies, for checking that a change
+// doesn't alter outcomes. The first strategy is player 0.
+pub fn run_simulation(args: &[String]) -> Result<()> {
+    let mut size = (20, 20);
+    let mut topology = Topology::Torus;
+    let mut seed = 0;
+    let mut time_budget = Duration::from_millis(10);
+    let mut print_frames = false;
+    let mut strategy_kinds: Vec<StrategyKind> = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--size" => {
+                let value = value()?.parse()?;
+                if value == 0 {
+                    return Err(anyhow!("size must be positive"));
+                }
+                size = (value, value);
+            }
+            "--seed" => seed = value()?.parse()?,
+            "--budget-ms" => time_budget = Duration::from_millis(value()?.parse()?),
+            "--bounded" => topology = Topology::Bounded,
+            "--frames" => print_frames = true,
+            name if name.starts_with("--") => return Err(anyhow!("unknown argument: {}", name)),
+            name => strategy_kinds.push(name.parse()?),
+        }
+    }
+    if strategy_kinds.is_empty() {
+        return Err(anyhow!("no strategies given"));
+    }
+    if strategy_kinds.len() > size.0 * size.1 {
+        return Err(anyhow!("more strategies than cells"));
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut strategy_config = StrategyConfig::from_env()?;
+    let mut strategies_by_player: Vec<Box<dyn Strategy>> = Vec::new();
+    for kind in strategy_kinds {
+        strategy_config.kind = kind;
+        strategies_by_player.push(strategy_config.build(&mut rng));
+    }
+    let start_positions =
+        tournament::random_start_positions(size, strategies_by_player.len(), &mut rng);
+
+    let outcome = tournament::run_game_with_frames(
+        size,
+        topology,
+        &mut strategies_by_player,
+        &start_positions,
+        time_budget,
+        |board| {
+            if print_frames {
+                println!("{}", board.render_ascii_with_heads());
+            }
+        },
+    );
+    match outcome.winner {
+        Some(player_id) => println!("winner {} after {} turns", player_id, outcome.turns),
+        None => println!("draw after {} turns", outcome.turns),
+    }
+
+    Ok(())
+}
+
+fn run_online(
+    strategy_config: StrategyConfig,
+    round_config: RoundConfig,
+    taunter: Option<Taunter>,
+) -> Result<()> {
+    let host_port =
+        std::env::var("GPN_SNAKE_HOST_PORT").unwrap_or("gpn-tron.duckdns.org:4000".to_string());
+    let accounts = accounts_from_env()?;
+    let mut rng = rng_from_env()?;
+
+    // Each account plays on its own connection and thread
+    let stats = Mutex::new(RoundStats::default());
+    std::thread::scope(|scope| {
+        for account in accounts {
+            let host_port = &host_port;
+            let strategy_config = &strategy_config;
+            let round_config = &round_config;
+            let stats = &stats;
+            let mut taunter = taunter.clone();
+            let mut rng = child_rng(&mut rng);
+            scope.spawn(move || {
+                run_connection(
+                    host_port,
+                    &account,
+                    strategy_config,
+                    round_config,
+                    taunter.as_mut(),
+                    &mut rng,
+                    stats,
+                )
+            });
+        }
+    });
+
+    info!("shutting down: {}", stats.lock().unwrap());
+    Ok(())
+}
+
+fn run_connection(
+    host_port: &str,
+    account: &(String, String),
+    strategy_config: &StrategyConfig,
+    round_config: &RoundConfig,
+    mut taunter: Option<&mut Taunter>,
+    rng: &mut StdRng,
+    stats: &Mutex<RoundStats>,
+) {
+    let username = &account.0;
+    let round_config = &RoundConfig {
+        username: username.clone(),
+        ..round_config.clone()
+    };
+    let mut retry = 0;
+    while !shutdown::requested() {
+        match try_play(
+            host_port,
+            account,
+            strategy_config,
+            round_config,
+            taunter.as_deref_mut(),
+            rng,
+            |outcome| {
+                let mut stats = stats.lock().unwrap();
+                stats.record(outcome);
+                info!("{} finished round with {:?}: {}", username, outcome, stats);
+            },
+        ) {
+            Ok(RoundOutcome::Interrupted) => break,
+            Ok(_) => retry = 0,
+            Err(_) if shutdown::requested() => break,
+            Err(err) => {
+                let backoff = reconnect_backoff(retry).mul_f64(rng.gen_range(0.5..=1.0));
+                warn!(
+                    "{} restarting in {} ms due to error: {:?}",
+                    username,
+                    backoff.as_millis(),
+                    err
+                );
+                std::thread::sleep(backoff);
+                retry += 1;
+            }
+        }
+    }
+}
+
+// GPN_SNAKE_ACCOUNTS is a list like "user1:pass1;user2:pass2". Without it, a
+// single account is read from GPN_SNAKE_USERNAME and GPN_SNAKE_PASSWORD.
+// The protocol has no separate token field, so GPN_SNAKE_TOKEN is sent as
+// the password if there is none. Servers without auth take an empty
+// password, which is also what an account without ":" gets.
+fn accounts_from_env() -> Result<Vec<(String, String)>> {
+    let accounts = match std::env::var("GPN_SNAKE_ACCOUNTS") {
+        Ok(accounts) => accounts
+            .split(';')
+            .map(|account| account.trim())
+            .filter(|account| !account.is_empty())
+            .map(|account| {
+                let (username, password) = account.split_once(':').unwrap_or((account, ""));
+                if username.is_empty() {
+                    return Err(anyhow!("account without username: {}", account));
+                }
+                Ok((username.to_string(), password.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        Err(_) => {
+            let username = std::env::var("GPN_SNAKE_USERNAME")
+                .map_err(|_| anyhow!("GPN_SNAKE_USERNAME is not set"))?;
+            let password = std::env::var("GPN_SNAKE_PASSWORD")
+                .or_else(|_| std::env::var("GPN_SNAKE_TOKEN"))
+                .unwrap_or_default();
+            vec![(username, password)]
+        }
+    };
+    if accounts.is_empty() {
+        return Err(anyhow!("no accounts configured"));
+    }
+    Ok(accounts)
+}
+
+fn reconnect_backoff(retry: u32) -> Duration {
+    let min_backoff = time::Duration::from_millis(50);
+    let max_backoff = time::Duration::from_secs(30);
+    min_backoff
+        .checked_mul(2u32.saturating_pow(retry))
+        .map_or(max_backoff, |backoff| backoff.min(max_backoff))
+}
+
+fn run_replay(
+    path: &std::path::Path,
+    strategy_config: &StrategyConfig,
+    round_config: &RoundConfig,
+) -> Result<()> {
+    let messages = replay::load_replay(path)?;
+    let mut rng = rng_from_env()?;
+    let mut strategy = strategy_config.build(&mut rng);
+
+    let mut board: Option<BoardTracker> = None;
+    let mut tick_budget = TickBudget::new(round_config.budget_fraction);
+    let mut last_tick_at: Option<Duration> = None;
+    let mut tick = 0;
+    let mut round_started_at = Duration::ZERO;
+    for (elapsed, msg) in messages {
+        match &msg {
+            ServerMessage::Game { message } => {
+                board = Some(message.new_board()?);
+                strategy.start(message);
+                tick_budget = TickBudget::new(round_config.budget_fraction);
+                last_tick_at = None;
+                tick = 0;
+                round_started_at = elapsed;
+            }
+            ServerMessage::Tick => {
+                let board = board
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("tick before game in replay"))?;
+                if let Some(last_tick_at) = last_tick_at {
+                    tick_budget.record_interval(elapsed.saturating_sub(last_tick_at));
+                }
+                last_tick_at = Some(elapsed);
+                let time_budget = tick_budget.budget();
+                let context = StepContext {
+                    tick,
+                    round_elapsed: elapsed.saturating_sub(round_started_at),
+                };
+                let direction = strategy.step_in_context(board, time_budget, &context);
+                println!("{}", board.render_ascii());
+                println!(
+                    "--- tick {} would move {} ({} ms budget) ---\n",
+                    tick,
+                    direction,
+                    time_budget.as_millis()
+                );
+                tick += 1;
+            }
+            _ => {
+                if let Some(board) = board.as_mut() {
+                    update_board(board, &msg, round_config.clear_dead_trails)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn tournament_strategies(rng: &mut StdRng) -> Vec<Box<dyn Strategy>> {
+    vec![
+        Box::new(PlayoutAfterNextStrategy::new(
+            PlayoutAfterNextStrategyMode::WinProbability,
+            120,
+            1,
+            0.0,
+            false,
+            0.5,
+            child_rng(rng),
+        )),
+        Box::new(UcbPlayoutStrategy::new(120, 1.0, 0.5, child_rng(rng))),
+        Box::new(GetAwayFromItAllStrategy::new(child_rng(rng))),
+        Box::new(NoCrashRandomStrategy::new(child_rng(rng))),
+        Box::new(SpaceFillStrategy::new()),
+        Box::new(WallHugStrategy::new()),
+        Box::new(HuntWeakestStrategy::new()),
+        Box::new(VoronoiGreedyStrategy::new()),
+    ]
+}
+
+fn run_tournament(args: &[String]) -> Result<()> {
+    let mut config = tournament::TournamentConfig {
+        size: (20, 20),
+        topology: Topology::Torus,
+        seed: 0,
+        games: 10,
+        time_budget: Duration::from_millis(10),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--size" => {
+                let size = value()?.parse()?;
+                if size == 0 {
+                    return Err(anyhow!("size must be positive"));
+                }
+                config.size = (size, size);
+            }
+            "--seed" => config.seed = value()?.parse()?,
+            "--games" => config.games = value()?.parse()?,
+            "--bounded" => config.topology = Topology::Bounded,
+            "--budget-ms" => config.time_budget = Duration::from_millis(value()?.parse()?),
+            _ => return Err(anyhow!("unknown argument: {}", arg)),
+        }
+    }
+
+    let result = tournament::run_tournament(&config, tournament_strategies);
+    println!("{:?}", result);
+
+    Ok(())
+}
+
+pub fn run() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|arg| arg.as_str()) == Some("--tournament") {
+        return run_tournament(&args[1..]);
+    }
+
+    let mut strategy_config = StrategyConfig::from_env()?;
+    let mut round_config = RoundConfig::from_env()?;
+    let mut taunter = Taunter::from_env()?;
+    let mut replay_path: Option<PathBuf> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strategy" => {
+                strategy_config.kind = args
+                    .next()
+                    .ok_or_else(|| anyhow!("missing value for {}", arg))?
+                    .parse()?;
+            }
+            "--taunts" => {
+                let taunts = parse_taunts(
+                    args.next()
+                        .ok_or_else(|| anyhow!("missing value for {}", arg))?,
+                )?;
+                let min_interval = taunter
+                    .as_ref()
+                    .map_or(Duration::from_secs(5), |taunter| taunter.min_interval);
+                taunter = if taunts.is_empty() {
+                    None
+                } else {
+                    Some(Taunter::new(taunts, min_interval))
+                };
+            }
+            "--replay" => {
+                replay_path = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow!("missing value for {}", arg))?
+                        .into(),
+                );
+            }
+            "--dry-run" => round_config.dry_run = true,
+            _ => return Err(anyhow!("unknown argument: {}", arg)),
+        }
+    }
+
+    if round_config.dry_run {
+        info!("dry run, moves and taunts will not be sent");
+        taunter = None;
+    }
+
+    if let Some(replay_path) = replay_path {
+        return run_replay(&replay_path, &strategy_config, &round_config);
+    }
+
+    shutdown::install_handler()?;
+    run_online(strategy_config, round_config, taunter)?;
+
+    Ok(())
+}
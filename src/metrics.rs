@@ -0,0 +1,108 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::direction::Direction;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectionScore {
+    pub direction: Direction,
+    pub mean_score: f64,
+    pub playouts: usize,
+}
+
+// Strategies fill in what they know about their decision, the round loop
+// adds the tick, the chosen direction and the timing
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StepTrace {
+    // Empty outside of online play
+    pub username: String,
+    pub tick: usize,
+    pub direction: Option<Direction>,
+    pub playouts: usize,
+    pub direction_scores: Vec<DirectionScore>,
+    pub calc_ms: u128,
+    pub budget_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heatmap: Option<Heatmap>,
+}
+
+// How often each cell ended up in our trail across the playouts of a step,
+// row-major like the board
+#[derive(Debug, Clone, Serialize)]
+pub struct Heatmap {
+    pub width: usize,
+    pub height: usize,
+    pub counts: Vec<usize>,
+}
+
+impl Heatmap {
+    pub fn new(size: (usize, usize)) -> Self {
+        let (width, height) = size;
+        Heatmap {
+            width,
+            height,
+            counts: vec![0; width * height],
+        }
+    }
+
+    pub fn add(&mut self, cells: &[(usize, usize)]) {
+        for &(x, y) in cells {
+            self.counts[y * self.width + x] += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Heatmap) {
+        assert_eq!(self.counts.len(), other.counts.len());
+        for (count, other_count) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other_count;
+        }
+    }
+
+    // Counts scaled to 0-9 relative to the highest count, '.' for cells we
+    // never visited
+    pub fn render_ascii(&self) -> String {
+        let max_count = self.counts.iter().copied().max().unwrap_or(0);
+        let mut output = String::new();
+        for row in self.counts.chunks(self.width) {
+            for &count in row {
+                output.push(if count == 0 {
+                    '.'
+                } else {
+                    char::from_digit((count * 9).div_ceil(max_count) as u32, 10).unwrap()
+                });
+            }
+            output.push('\n');
+        }
+        output
+    }
+}
+
+// Appends one JSON object per line
+pub struct MetricsWriter {
+    inner: BufWriter<File>,
+}
+
+impl MetricsWriter {
+    pub fn open(path: &Path) -> Result<MetricsWriter> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(MetricsWriter {
+            inner: BufWriter::new(file),
+        })
+    }
+
+    // Each line goes out in a single write, so that the lines of several
+    // accounts appending to the same file don't interleave
+    pub fn write(&mut self, trace: &StepTrace) -> Result<()> {
+        let mut line = serde_json::to_vec(trace)?;
+        line.push(b'\n');
+        self.inner.write_all(&line)?;
+        self.inner.flush()?;
+        Ok(())
+    }
+}
@@ -1,90 +1,405 @@
-use std::time::Duration;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
 
-use crate::{board_tracker::BoardTracker, Strategy};
+use crate::{board_tracker::BoardTracker, direction::Direction, reachability, voronoi, Strategy};
+
+#[derive(Clone, Copy, Debug)]
+pub struct PlayoutOptions {
+    pub max_steps: usize,
+    pub clear_on_death: bool,
+    // Compute territory_share for playouts that run out of steps
+    pub measure_territory: bool,
+    // Count it as a win as soon as our Voronoi region is larger than all
+    // opponents' regions combined. Costs a Voronoi computation per turn.
+    pub stop_on_territory_majority: bool,
+    // End the playout once no opponent can reach the cells we can reach, and
+    // decide it by who has more room. Costs a connected components pass per turn.
+    pub stop_on_separation: bool,
+    // Fill in own_cells in the result
+    pub record_own_cells: bool,
+    // Split evenly across max_steps and passed to each strategy's step.
+    // Zero for strategies that don't look at their budget.
+    pub time_budget: Duration,
+}
+
+impl PlayoutOptions {
+    pub fn new(max_steps: usize, clear_on_death: bool) -> Self {
+        Self {
+            max_steps,
+            clear_on_death,
+            measure_territory: false,
+            stop_on_territory_majority: false,
+            stop_on_separation: false,
+            record_own_cells: false,
+            time_budget: Duration::ZERO,
+        }
+    }
+}
+
+// Playout depth for a board with the given fraction of free cells. Scales
+// linearly from max_steps / MIN_DEPTH_DIVISOR on a full board up to max_steps
+// on an empty one.
+pub fn depth_for_free_fraction(max_steps: usize, free_fraction: f64) -> usize {
+    const MIN_DEPTH_DIVISOR: usize = 5;
+    assert!(max_steps > 0);
+    let min_depth = (max_steps / MIN_DEPTH_DIVISOR).max(1);
+    let free_fraction = free_fraction.clamp(0.0, 1.0);
+    let depth = min_depth as f64 + (max_steps - min_depth) as f64 * free_fraction;
+    (depth.round() as usize).clamp(min_depth, max_steps)
+}
 
 pub struct PlayoutResult {
+    // Players that died during the playout, not counting those already dead
     pub beaten_players: usize,
     pub remaining_players: usize,
     pub survived_steps: usize,
     pub did_win: bool,
     pub did_die: bool,
+    // We died in the same turn as all remaining players, so nobody won
+    pub did_draw: bool,
+    // Only set for playouts that ran out of steps with measure_territory
+    pub territory_share: Option<f64>,
+    // Cells we moved into during the playout, only with record_own_cells
+    pub own_cells: Vec<(usize, usize)>,
+}
+
+// Memoizes territory_share within a step, since playouts that start with
+// different moves often end up in the same position
+#[derive(Default)]
+pub struct TerritoryCache {
+    shares: HashMap<(u64, usize), f64>,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl TerritoryCache {
+    pub fn clear(&mut self) {
+        self.shares.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
 }
 
 pub fn run_playout(
-    mut board: BoardTracker,
+    board: &mut BoardTracker,
+    strategies_by_player: Vec<Box<dyn Strategy>>,
+    own_player_id: usize,
+    options: PlayoutOptions,
+    territory_cache: Option<&Mutex<TerritoryCache>>,
+) -> PlayoutResult {
+    let trail_len_at_start = board.player_trail(own_player_id).len();
+    board.checkpoint();
+    let mut result = run_playout_inner(
+        board,
+        strategies_by_player,
+        own_player_id,
+        options,
+        territory_cache,
+    );
+    if options.record_own_cells {
+        result.own_cells = board.player_trail(own_player_id)[trail_len_at_start..].to_vec();
+    }
+    board.rollback();
+    result
+}
+
+fn run_playout_inner(
+    board: &mut BoardTracker,
     mut strategies_by_player: Vec<Box<dyn Strategy>>,
     own_player_id: usize,
-    max_steps: usize,
-    clear_on_death: bool,
+    options: PlayoutOptions,
+    territory_cache: Option<&Mutex<TerritoryCache>>,
 ) -> PlayoutResult {
     assert!(!board.is_dead(own_player_id));
-    assert!(max_steps > 0);
-
-    let (width, _height) = board.board_size();
+    assert!(options.max_steps > 0);
 
+    let step_time_budget = options.time_budget.div_f64(options.max_steps as f64);
+    let count_dead_at_start = board.count_dead();
     for i_step in 0.. {
         let count_dead_before_turn = board.count_dead();
+        let count_alive_before_turn = board.count_alive();
 
-        let new_pos_by_player: Vec<Option<(usize, usize)>> = strategies_by_player
-            .iter_mut()
-            .enumerate()
-            .map(|(player_id, strategy)| {
-                if board.is_dead(player_id) {
-                    None
-                } else {
-                    let direction = strategy.step(&board, Duration::from_secs(0));
-                    let old_pos = board.get_player_latest_pos(player_id).unwrap();
-                    let new_pos = board.offset_pos(old_pos, direction);
-                    Some(new_pos)
-                }
-            })
-            .collect();
-
-        let mut next_occupied_count: Vec<usize> =
-            board.occupied_mask().iter().map(|v| *v as usize).collect();
-        for new_pos in new_pos_by_player.iter().flatten() {
-            let new_i = new_pos.1 * width + new_pos.0;
-            next_occupied_count[new_i] += 1;
-        }
-
-        for (player_id, new_pos) in new_pos_by_player.iter().enumerate() {
-            if let &Some(new_pos) = new_pos {
-                let new_i = new_pos.1 * width + new_pos.0;
-                if next_occupied_count[new_i] == 1 {
-                    board.record_pos(player_id, new_pos);
-                } else {
-                    board.record_death(player_id, clear_on_death);
-                }
-            }
-        }
+        run_turn(
+            board,
+            &mut strategies_by_player,
+            step_time_budget,
+            options.clear_on_death,
+        );
 
         if board.is_dead(own_player_id) {
             return PlayoutResult {
-                beaten_players: count_dead_before_turn,
+                beaten_players: count_dead_before_turn - count_dead_at_start,
                 remaining_players: board.count_alive(),
                 survived_steps: i_step,
                 did_win: false,
                 did_die: true,
+                did_draw: count_alive_before_turn > 1 && board.count_alive() == 0,
+                own_cells: Vec::new(),
+                territory_share: None,
             };
-        } else if board.count_alive() == 1 {
+        } else if board.count_alive() == 1
+            || (options.stop_on_territory_majority && has_territory_majority(board, own_player_id))
+        {
             return PlayoutResult {
-                beaten_players: board.count_dead(),
+                beaten_players: board.count_dead() - count_dead_at_start,
                 remaining_players: board.count_alive(),
                 survived_steps: i_step + 1,
                 did_win: true,
                 did_die: false,
+                did_draw: false,
+                own_cells: Vec::new(),
+                territory_share: None,
             };
-        } else if i_step + 1 >= max_steps {
-            assert!(i_step + 1 == max_steps);
+        }
+
+        if options.stop_on_separation {
+            if let Some((own_room, other_room)) = separated_room(board, own_player_id) {
+                // We can keep going for about as many steps as we have cells
+                let survived_steps = i_step + 1 + own_room.min(options.max_steps - (i_step + 1));
+                return PlayoutResult {
+                    beaten_players: board.count_dead() - count_dead_at_start,
+                    remaining_players: board.count_alive(),
+                    survived_steps,
+                    did_win: own_room > other_room,
+                    did_die: own_room <= other_room,
+                    did_draw: own_room == other_room,
+                    own_cells: Vec::new(),
+                    territory_share: None,
+                };
+            }
+        }
+
+        if i_step + 1 >= options.max_steps {
+            assert!(i_step + 1 == options.max_steps);
             return PlayoutResult {
-                beaten_players: board.count_dead(),
+                beaten_players: board.count_dead() - count_dead_at_start,
                 remaining_players: board.count_alive(),
                 survived_steps: i_step + 1,
                 did_win: false,
                 did_die: false,
+                did_draw: false,
+                own_cells: Vec::new(),
+                territory_share: options
+                    .measure_territory
+                    .then(|| cached_territory_share(board, own_player_id, territory_cache)),
             };
         }
     }
 
     panic!("step overflow")
 }
+
+// The largest region we can move into and the largest region any opponent
+// can move into, or None while some opponent can still reach one of ours
+fn separated_room(board: &BoardTracker, own_player_id: usize) -> Option<(usize, usize)> {
+    let (width, _height) = board.board_size();
+    let (labels, component_sizes) = reachability::connected_components(
+        board.board_size(),
+        board.topology(),
+        board.occupied_cells(),
+    );
+    let labels_next_to = |player_id: usize| -> Vec<usize> {
+        let head = match board.get_player_latest_pos(player_id) {
+            Some(head) => head,
+            None => return Vec::new(),
+        };
+        Direction::all_directions()
+            .into_iter()
+            .filter_map(|direction| board.neighbor_pos(head, direction))
+            .filter_map(|pos| labels[pos.1 * width + pos.0])
+            .collect()
+    };
+    let room = |labels: &[usize]| {
+        labels
+            .iter()
+            .map(|&label| component_sizes[label])
+            .max()
+            .unwrap_or(0)
+    };
+
+    let own_labels = labels_next_to(own_player_id);
+    let mut other_room = 0;
+    for player_id in 0..board.count_seen() {
+        if player_id == own_player_id || board.is_dead(player_id) {
+            continue;
+        }
+        let other_labels = labels_next_to(player_id);
+        if other_labels.iter().any(|label| own_labels.contains(label)) {
+            return None;
+        }
+        other_room = other_room.max(room(&other_labels));
+    }
+    Some((room(&own_labels), other_room))
+}
+
+fn cached_territory_share(
+    board: &BoardTracker,
+    own_player_id: usize,
+    territory_cache: Option<&Mutex<TerritoryCache>>,
+) -> f64 {
+    let territory_cache = match territory_cache {
+        Some(territory_cache) => territory_cache,
+        None => return territory_share(board, own_player_id),
+    };
+    let key = (board.state_hash(), own_player_id);
+    {
+        let mut territory_cache = territory_cache.lock().unwrap();
+        if let Some(&share) = territory_cache.shares.get(&key) {
+            territory_cache.hits += 1;
+            return share;
+        }
+        territory_cache.misses += 1;
+    }
+    // Computed without holding the lock, so other threads aren't blocked
+    let share = territory_share(board, own_player_id);
+    territory_cache.lock().unwrap().shares.insert(key, share);
+    share
+}
+
+// Our share of the free cells that we reach before any other living player
+fn territory_share(board: &BoardTracker, own_player_id: usize) -> f64 {
+    let (own_count, other_count) = territory_counts(board, own_player_id);
+    if own_count + other_count == 0 {
+        return 0.0;
+    }
+    own_count as f64 / (own_count + other_count) as f64
+}
+
+fn has_territory_majority(board: &BoardTracker, own_player_id: usize) -> bool {
+    let (own_count, other_count) = territory_counts(board, own_player_id);
+    own_count > other_count
+}
+
+// Cells in our Voronoi region and in all opponents' regions combined
+fn territory_counts(board: &BoardTracker, own_player_id: usize) -> (usize, usize) {
+    let living_players: Vec<(usize, (usize, usize))> = (0..board.count_seen())
+        .filter(|&player_id| !board.is_dead(player_id))
+        .filter_map(|player_id| Some((player_id, board.get_player_latest_pos(player_id)?)))
+        .collect();
+    let heads: Vec<_> = living_players.iter().map(|&(_, pos)| pos).collect();
+    let own_index = living_players
+        .iter()
+        .position(|&(player_id, _)| player_id == own_player_id)
+        .unwrap();
+
+    let owners = voronoi::voronoi_control(
+        board.board_size(),
+        board.topology(),
+        board.occupied_cells(),
+        &heads,
+    );
+    let controlled_count = owners.iter().filter(|owner| owner.is_some()).count();
+    let own_count = voronoi::count_controlled(&owners, own_index);
+    (own_count, controlled_count - own_count)
+}
+
+// Moves every living player at once. A move kills the player if its cell
+// was already occupied by any trail, including the player's own (so reversing
+// is fatal), or if another player moves into the same cell. Counts are taken
+// before any deaths are recorded, so trails cleared this turn still kill.
+// On a bounded board, moving off the edge is fatal too.
+pub fn run_turn(
+    board: &mut BoardTracker,
+    strategies_by_player: &mut [Box<dyn Strategy>],
+    time_budget: Duration,
+    clear_on_death: bool,
+) {
+    let (width, _height) = board.board_size();
+
+    // The inner None is a move off the edge of a bounded board
+    let moves_by_player: Vec<Option<Option<(usize, usize)>>> = strategies_by_player
+        .iter_mut()
+        .enumerate()
+        .map(|(player_id, strategy)| {
+            // Players that were only mentioned in a die message have no position
+            let old_pos = board.get_player_latest_pos(player_id)?;
+            if board.is_dead(player_id) {
+                None
+            } else {
+                let direction = strategy.step(board, time_budget);
+                Some(board.neighbor_pos(old_pos, direction))
+            }
+        })
+        .collect();
+    let new_pos_by_player: Vec<Option<(usize, usize)>> = moves_by_player
+        .iter()
+        .map(|&new_pos| new_pos.flatten())
+        .collect();
+
+    let next_occupied_count = next_occupied_count(board, &new_pos_by_player);
+    for (player_id, &new_pos) in moves_by_player.iter().enumerate() {
+        match new_pos {
+            None => (),
+            Some(Some(new_pos)) if next_occupied_count[new_pos.1 * width + new_pos.0] == 1 => {
+                board.record_pos(player_id, new_pos);
+            }
+            Some(_) => board.record_death(player_id, clear_on_death),
+        }
+    }
+}
+
+// Trails plus new heads for each cell. Trails count 1 on their own, so a move
+// only survives if its cell was free and nobody else moved there, i.e. the
+// count is exactly 1.
+fn next_occupied_count(
+    board: &BoardTracker,
+    new_pos_by_player: &[Option<(usize, usize)>],
+) -> Vec<usize> {
+    let (width, _height) = board.board_size();
+    let mut next_occupied_count: Vec<usize> =
+        board.occupied_cells().iter().map(|v| *v as usize).collect();
+    for new_pos in new_pos_by_player.iter().flatten() {
+        let new_i = new_pos.1 * width + new_pos.0;
+        next_occupied_count[new_i] += 1;
+    }
+    next_occupied_count
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContestedCell {
+    pub pos: (usize, usize),
+    pub own_direction: Direction,
+    pub other_direction: Direction,
+    // This is our only move that doesn't hit a trail
+    pub own_forced: bool,
+    // This is the other player's only move that doesn't hit a trail
+    pub other_forced: bool,
+}
+
+// Free cells that both players could move into next turn. Everyone entering
+// a shared cell dies (see run_turn), so each of these is a mutual death if
+// both take it, which in 1v1 is a draw: worth avoiding when we're ahead, and
+// worth taking when all our other moves lose, especially if the other player
+// is forced.
+pub fn contested_cells(
+    board: &BoardTracker,
+    own_player_id: usize,
+    other_player_id: usize,
+) -> Vec<ContestedCell> {
+    let (own_head, other_head) = match (
+        board.get_player_latest_pos(own_player_id),
+        board.get_player_latest_pos(other_player_id),
+    ) {
+        (Some(own_head), Some(other_head)) => (own_head, other_head),
+        _ => return Vec::new(),
+    };
+    let own_moves = board.safe_moves(own_player_id);
+    let other_moves = board.safe_moves(other_player_id);
+
+    let mut contested = Vec::new();
+    for &own_direction in &own_moves {
+        let pos = board.neighbor_pos(own_head, own_direction).unwrap();
+        for &other_direction in &other_moves {
+            if board.neighbor_pos(other_head, other_direction) != Some(pos) {
+                continue;
+            }
+            contested.push(ContestedCell {
+                pos,
+                own_direction,
+                other_direction,
+                own_forced: own_moves.len() == 1,
+                other_forced: other_moves.len() == 1,
+            });
+        }
+    }
+    contested
+}
@@ -1,10 +1,21 @@
-use crate::direction::Direction;
+use crate::board_tracker::CellMask;
+use crate::direction::{Direction, Topology};
 
 pub fn calculate_reachable(
     size: (usize, usize),
-    occupied_mask: &[bool],
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
     start_pos: (usize, usize),
 ) -> Vec<bool> {
+    calculate_reachable_with_count(size, topology, occupied_mask, start_pos).0
+}
+
+pub fn calculate_reachable_with_count(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+    start_pos: (usize, usize),
+) -> (Vec<bool>, usize) {
     let (width, height) = size;
 
     assert_eq!(occupied_mask.len(), width * height);
@@ -14,21 +25,224 @@ pub fn calculate_reachable(
 
     let start_i = start_pos.1 * width + start_pos.0;
     reachable[start_i] = true;
+    let mut count = 1;
     queue.push_back(start_i);
 
     while let Some(current) = queue.pop_front() {
         let pos = (current % width, current / width);
 
         for direction in Direction::all_directions() {
-            let new_pos = direction.offset_pos(pos, size);
+            let new_pos = match topology.offset_pos(pos, direction, size) {
+                Some(new_pos) => new_pos,
+                None => continue,
+            };
             let new_i = new_pos.1 * width + new_pos.0;
 
-            if !reachable[new_i] && !occupied_mask[new_i] {
+            if !reachable[new_i] && !occupied_mask.get(new_i) {
                 reachable[new_i] = true;
+                count += 1;
                 queue.push_back(new_i);
             }
         }
     }
 
-    reachable
+    (reachable, count)
+}
+
+// The space we'd have after moving from head in each direction, indexed like
+// Direction::all_directions(). Counts the cell we move into, but doesn't
+// allow passing back through it. None if the move crashes immediately,
+// into a trail or, on a bounded board, the edge.
+pub fn reachable_after_each_move(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+    head: (usize, usize),
+) -> [Option<usize>; 4] {
+    let (width, _height) = size;
+
+    Direction::all_directions().map(|direction| {
+        let new_pos = topology.offset_pos(head, direction, size)?;
+        let new_i = new_pos.1 * width + new_pos.0;
+        if occupied_mask.get(new_i) {
+            return None;
+        }
+        let mask_after_move = WithOccupiedCell {
+            inner: occupied_mask,
+            occupied_i: new_i,
+        };
+        Some(calculate_reachable_with_count(size, topology, &mask_after_move, new_pos).1)
+    })
+}
+
+// Like reachable_after_each_move, but if the cell we move into is a neck
+// between separate regions, only the largest region counts, since we can
+// only go into one of them. Comparing this with our remaining length tells
+// whether a move commits us to a dead end.
+pub fn pocket_size_after_each_move(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &[bool],
+    head: (usize, usize),
+) -> [Option<usize>; 4] {
+    let (width, _height) = size;
+
+    Direction::all_directions().map(|direction| {
+        let new_pos = topology.offset_pos(head, direction, size)?;
+        let new_i = new_pos.1 * width + new_pos.0;
+        if occupied_mask[new_i] {
+            return None;
+        }
+
+        let mut mask_after_move = occupied_mask.to_vec();
+        mask_after_move[new_i] = true;
+        let (labels, component_sizes) = connected_components(size, topology, &mask_after_move);
+        let largest_neighbor_component = Direction::all_directions()
+            .iter()
+            .filter_map(|&neighbor_direction| {
+                let neighbor_pos = topology.offset_pos(new_pos, neighbor_direction, size)?;
+                labels[neighbor_pos.1 * width + neighbor_pos.0]
+            })
+            .map(|label| component_sizes[label])
+            .max()
+            .unwrap_or(0);
+        Some(1 + largest_neighbor_component)
+    })
+}
+
+struct WithOccupiedCell<'a, M: CellMask + ?Sized> {
+    inner: &'a M,
+    occupied_i: usize,
+}
+
+impl<M: CellMask + ?Sized> CellMask for WithOccupiedCell<'_, M> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn get(&self, i: usize) -> bool {
+        i == self.occupied_i || self.inner.get(i)
+    }
+}
+
+// Stops once cap cells have been found, so the cost doesn't grow with the board.
+// Only suitable for small caps, since visited cells are searched linearly.
+pub fn count_reachable_capped(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+    start_pos: (usize, usize),
+    cap: usize,
+) -> usize {
+    let (width, height) = size;
+
+    assert_eq!(occupied_mask.len(), width * height);
+
+    let mut visited = vec![start_pos.1 * width + start_pos.0];
+    let mut i_next = 0;
+    while i_next < visited.len() && visited.len() < cap {
+        let current = visited[i_next];
+        i_next += 1;
+        let pos = (current % width, current / width);
+
+        for direction in Direction::all_directions() {
+            let new_pos = match topology.offset_pos(pos, direction, size) {
+                Some(new_pos) => new_pos,
+                None => continue,
+            };
+            let new_i = new_pos.1 * width + new_pos.0;
+
+            if !occupied_mask.get(new_i) && !visited.contains(&new_i) {
+                visited.push(new_i);
+            }
+        }
+    }
+
+    visited.len().min(cap)
+}
+
+// Free cells at most radius steps from start_pos, including start_pos itself.
+// Like count_reachable_capped, this is meant for small radii.
+pub fn count_reachable_within(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+    start_pos: (usize, usize),
+    radius: usize,
+) -> usize {
+    let (width, height) = size;
+
+    assert_eq!(occupied_mask.len(), width * height);
+
+    let mut visited = vec![(start_pos.1 * width + start_pos.0, 0)];
+    let mut i_next = 0;
+    while i_next < visited.len() {
+        let (current, distance) = visited[i_next];
+        i_next += 1;
+        if distance == radius {
+            continue;
+        }
+        let pos = (current % width, current / width);
+
+        for direction in Direction::all_directions() {
+            let new_pos = match topology.offset_pos(pos, direction, size) {
+                Some(new_pos) => new_pos,
+                None => continue,
+            };
+            let new_i = new_pos.1 * width + new_pos.0;
+
+            if !occupied_mask.get(new_i) && !visited.iter().any(|&(i, _)| i == new_i) {
+                visited.push((new_i, distance + 1));
+            }
+        }
+    }
+
+    visited.len()
+}
+
+pub fn connected_components(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &[bool],
+) -> (Vec<Option<usize>>, Vec<usize>) {
+    let (width, height) = size;
+
+    assert_eq!(occupied_mask.len(), width * height);
+
+    let mut labels: Vec<Option<usize>> = vec![None; width * height];
+    let mut component_sizes = Vec::new();
+    let mut queue = std::collections::VecDeque::new();
+
+    for start_i in 0..occupied_mask.len() {
+        if occupied_mask[start_i] || labels[start_i].is_some() {
+            continue;
+        }
+
+        let label = component_sizes.len();
+        let mut component_size = 1;
+        labels[start_i] = Some(label);
+        queue.push_back(start_i);
+
+        while let Some(current) = queue.pop_front() {
+            let pos = (current % width, current / width);
+
+            for direction in Direction::all_directions() {
+                let new_pos = match topology.offset_pos(pos, direction, size) {
+                    Some(new_pos) => new_pos,
+                    None => continue,
+                };
+                let new_i = new_pos.1 * width + new_pos.0;
+
+                if labels[new_i].is_none() && !occupied_mask[new_i] {
+                    labels[new_i] = Some(label);
+                    component_size += 1;
+                    queue.push_back(new_i);
+                }
+            }
+        }
+
+        component_sizes.push(component_size);
+    }
+
+    (labels, component_sizes)
 }
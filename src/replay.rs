@@ -0,0 +1,89 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::ServerMessage;
+
+// Each line of a replay file is "<ms since recording started>\t<protocol line>"
+pub struct ReplayRecorder {
+    inner: BufWriter<File>,
+    started_at: Instant,
+}
+
+// Accounts play on their own threads and can start rounds in the same millisecond
+static RECORDING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+impl ReplayRecorder {
+    pub fn create(dir: &Path, username: &str) -> Result<(ReplayRecorder, PathBuf)> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let count = RECORDING_COUNT.fetch_add(1, Ordering::Relaxed);
+        // The username ends up in a file name, so only keep harmless characters
+        let username: String = username
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path = dir.join(format!("round-{}-{}-{}.replay", timestamp, username, count));
+        // Never overwrite another recording
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let recorder = ReplayRecorder {
+            inner: BufWriter::new(file),
+            started_at: Instant::now(),
+        };
+        Ok((recorder, path))
+    }
+
+    pub fn record(&mut self, message: &ServerMessage) -> Result<()> {
+        writeln!(
+            self.inner,
+            "{}\t{}",
+            self.started_at.elapsed().as_millis(),
+            message.encode()
+        )?;
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+pub fn parse_replay<R: BufRead>(reader: R) -> Result<Vec<(Duration, ServerMessage)>> {
+    reader
+        .lines()
+        .map(|line| {
+            let line = line?;
+            let (elapsed_ms, message) = line
+                .split_once('\t')
+                .ok_or_else(|| anyhow!("invalid replay line: {}", line))?;
+            Ok((
+                Duration::from_millis(elapsed_ms.parse()?),
+                ServerMessage::parse(message)?,
+            ))
+        })
+        .collect()
+}
+
+pub fn load_replay(path: &Path) -> Result<Vec<(Duration, ServerMessage)>> {
+    parse_replay(BufReader::new(File::open(path)?))
+}
+
+// Re-encodes the messages in the server wire format, so that a GameReader can
+// read a replay as if it came from the server
+pub fn to_protocol_bytes(messages: &[(Duration, ServerMessage)]) -> Vec<u8> {
+    messages
+        .iter()
+        .flat_map(|(_, message)| format!("{}\n", message.encode()).into_bytes())
+        .collect()
+}
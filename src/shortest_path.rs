@@ -1,61 +1,104 @@
-use crate::direction::Direction;
-use petgraph::graph::UnGraph;
+use crate::board_tracker::CellMask;
+use crate::direction::{Direction, Topology};
 
-pub fn shortest_path_next_direction(
+pub struct GridGraph {
     size: (usize, usize),
-    occupied_mask: &[bool],
-    start_pos: (usize, usize),
-    target_pos: (usize, usize),
-) -> Option<Direction> {
-    let (width, height) = size;
-    let start_i = start_pos.1 * width + start_pos.0;
-    let target_i = target_pos.1 * width + target_pos.0;
-
-    assert_eq!(occupied_mask.len(), width * height);
-
-    let g = UnGraph::<(), (), usize>::from_edges((0..occupied_mask.len()).flat_map(|i| {
-        let pos = (i % width, i / width);
-        [Direction::Right, Direction::Down]
-            .iter()
-            .flat_map(move |&direction| {
-                let new_pos = direction.offset_pos(pos, size);
-                let new_i = new_pos.1 * width + new_pos.0;
-                if (!occupied_mask[i] || pos == start_pos)
-                    && (!occupied_mask[new_i] || new_pos == start_pos)
-                {
-                    Some((i, new_i))
-                } else {
-                    None
-                }
+    neighbors: Vec<[usize; 4]>,
+}
+
+impl GridGraph {
+    // Moves off a bounded board lead back to the same cell, which the search
+    // has always visited already
+    pub fn new(size: (usize, usize), topology: Topology) -> Self {
+        let (width, height) = size;
+        let neighbors = (0..width * height)
+            .map(|i| {
+                let pos = (i % width, i / width);
+                Direction::all_directions().map(|direction| {
+                    match topology.offset_pos(pos, direction, size) {
+                        Some(new_pos) => new_pos.1 * width + new_pos.0,
+                        None => i,
+                    }
+                })
             })
-    }));
-
-    let path = petgraph::algo::astar(
-        &g,
-        start_i.into(),
-        |finish| finish == target_i.into(),
-        |_| 1,
-        |_| 0,
-    );
-
-    if let Some((_, path)) = path {
-        if path.len() >= 2 {
-            let a_i = path[0].index();
-            let b_i = path[1].index();
-
-            let a_pos = (a_i % width, a_i / width);
-            let b_pos = (b_i % width, b_i / width);
-
-            for direction in Direction::all_directions() {
-                if direction.offset_pos(a_pos, size) == b_pos {
-                    return Some(direction);
+            .collect();
+        Self { size, neighbors }
+    }
+
+    pub fn next_direction(
+        &self,
+        occupied_mask: &(impl CellMask + ?Sized),
+        start_pos: (usize, usize),
+        target_pos: (usize, usize),
+    ) -> Option<Direction> {
+        self.next_direction_to_any(occupied_mask, start_pos, &[target_pos])
+    }
+
+    pub fn next_direction_to_any(
+        &self,
+        occupied_mask: &(impl CellMask + ?Sized),
+        start_pos: (usize, usize),
+        targets: &[(usize, usize)],
+    ) -> Option<Direction> {
+        let (width, height) = self.size;
+        let start_i = start_pos.1 * width + start_pos.0;
+
+        assert_eq!(occupied_mask.len(), width * height);
+
+        let mut is_target = vec![false; width * height];
+        for target_pos in targets {
+            is_target[target_pos.1 * width + target_pos.0] = true;
+        }
+
+        let mut first_direction: Vec<Option<Direction>> = vec![None; width * height];
+        let mut visited = vec![false; width * height];
+        let mut queue = std::collections::VecDeque::new();
+
+        visited[start_i] = true;
+        queue.push_back(start_i);
+
+        while let Some(current) = queue.pop_front() {
+            if current != start_i && is_target[current] {
+                return first_direction[current];
+            }
+
+            for (direction, &new_i) in Direction::all_directions()
+                .iter()
+                .zip(self.neighbors[current].iter())
+            {
+                if visited[new_i] || occupied_mask.get(new_i) {
+                    continue;
                 }
+                visited[new_i] = true;
+                first_direction[new_i] = if current == start_i {
+                    Some(*direction)
+                } else {
+                    first_direction[current]
+                };
+                queue.push_back(new_i);
             }
-            panic!("steps in path aren't adjacent")
-        } else {
-            None
         }
-    } else {
+
         None
     }
 }
+
+pub fn shortest_path_next_direction(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+    start_pos: (usize, usize),
+    target_pos: (usize, usize),
+) -> Option<Direction> {
+    GridGraph::new(size, topology).next_direction(occupied_mask, start_pos, target_pos)
+}
+
+pub fn shortest_path_to_any(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &(impl CellMask + ?Sized),
+    start_pos: (usize, usize),
+    targets: &[(usize, usize)],
+) -> Option<Direction> {
+    GridGraph::new(size, topology).next_direction_to_any(occupied_mask, start_pos, targets)
+}
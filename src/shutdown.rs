@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+pub fn request() {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// The first SIGINT only sets the flag so that the current round can end
+// cleanly. The default handler is restored, so a second SIGINT kills us.
+#[cfg(unix)]
+extern "C" fn handle_sigint(_: libc::c_int) {
+    request();
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+#[cfg(unix)]
+pub fn install_handler() -> Result<()> {
+    let handler = handle_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGINT, handler) } == libc::SIG_ERR {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install_handler() -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::{board_tracker::BoardTracker, direction::Topology, playout, GameInfo, Strategy};
+
+pub struct TournamentConfig {
+    pub size: (usize, usize),
+    pub topology: Topology,
+    pub seed: u64,
+    pub games: usize,
+    pub time_budget: Duration,
+}
+
+#[derive(Debug)]
+pub struct TournamentResult {
+    pub wins: Vec<usize>,
+    pub draws: usize,
+}
+
+#[derive(Debug)]
+pub struct GameOutcome {
+    pub winner: Option<usize>,
+    pub turns: usize,
+}
+
+pub fn run_game(
+    size: (usize, usize),
+    topology: Topology,
+    strategies_by_player: &mut [Box<dyn Strategy>],
+    start_positions: &[(usize, usize)],
+    time_budget: Duration,
+) -> Option<usize> {
+    run_game_with_frames(
+        size,
+        topology,
+        strategies_by_player,
+        start_positions,
+        time_budget,
+        |_| (),
+    )
+    .winner
+}
+
+// on_frame sees the board after the start positions and after every turn
+pub fn run_game_with_frames(
+    size: (usize, usize),
+    topology: Topology,
+    strategies_by_player: &mut [Box<dyn Strategy>],
+    start_positions: &[(usize, usize)],
+    time_budget: Duration,
+    mut on_frame: impl FnMut(&BoardTracker),
+) -> GameOutcome {
+    let (width, height) = size;
+    assert_eq!(strategies_by_player.len(), start_positions.len());
+    assert!(!strategies_by_player.is_empty());
+
+    let mut board = BoardTracker::new(width, height).with_topology(topology);
+    for (player_id, (strategy, &pos)) in strategies_by_player
+        .iter_mut()
+        .zip(start_positions)
+        .enumerate()
+    {
+        strategy.start(&GameInfo {
+            width: width.try_into().unwrap(),
+            height: height.try_into().unwrap(),
+            player_id: player_id.try_into().unwrap(),
+        });
+        board.record_pos(player_id, pos);
+    }
+    on_frame(&board);
+
+    // Dead trails are cleared, but between two deaths every turn grows the
+    // trails of the living, which can't outgrow the board. Everyone dies at
+    // most once, so this bound is never hit.
+    let max_turns = (strategies_by_player.len() + 1) * width * height;
+    let mut turns = 0;
+    for _ in 0..max_turns {
+        if board.count_alive() <= 1 {
+            break;
+        }
+        playout::run_turn(&mut board, strategies_by_player, time_budget, true);
+        turns += 1;
+        on_frame(&board);
+    }
+
+    if board.count_alive() > 1 {
+        warn!(
+            "stopped game after {} turns with {} players alive",
+            turns,
+            board.count_alive()
+        );
+    }
+
+    let winner = if board.count_alive() == 1 {
+        (0..strategies_by_player.len()).find(|&player_id| !board.is_dead(player_id))
+    } else {
+        None
+    };
+    GameOutcome { winner, turns }
+}
+
+pub fn random_start_positions(
+    size: (usize, usize),
+    n_players: usize,
+    rng: &mut StdRng,
+) -> Vec<(usize, usize)> {
+    let (width, height) = size;
+    assert!(n_players <= width * height);
+    let cells: Vec<(usize, usize)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .collect();
+    cells.choose_multiple(rng, n_players).cloned().collect()
+}
+
+pub fn run_tournament(
+    config: &TournamentConfig,
+    mut make_strategies: impl FnMut(&mut StdRng) -> Vec<Box<dyn Strategy>>,
+) -> TournamentResult {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+
+    let mut result = TournamentResult {
+        wins: Vec::new(),
+        draws: 0,
+    };
+
+    for i_game in 0..config.games {
+        let mut strategies_by_player = make_strategies(&mut rng);
+        let n_players = strategies_by_player.len();
+        result.wins.resize(n_players, 0);
+
+        let start_positions = random_start_positions(config.size, n_players, &mut rng);
+
+        let winner = run_game(
+            config.size,
+            config.topology,
+            &mut strategies_by_player,
+            &start_positions,
+            config.time_budget,
+        );
+        info!("game {} winner {:?}", i_game, winner);
+
+        match winner {
+            Some(player_id) => result.wins[player_id] += 1,
+            None => result.draws += 1,
+        }
+    }
+
+    result
+}
@@ -0,0 +1,61 @@
+use crate::direction::{Direction, Topology};
+
+pub fn voronoi_control(
+    size: (usize, usize),
+    topology: Topology,
+    occupied_mask: &[bool],
+    heads: &[(usize, usize)],
+) -> Vec<Option<usize>> {
+    let (width, height) = size;
+
+    assert_eq!(occupied_mask.len(), width * height);
+
+    let mut owners: Vec<Option<usize>> = vec![None; width * height];
+    let mut distances = vec![usize::MAX; width * height];
+    let mut queue = std::collections::VecDeque::new();
+
+    for (player, head) in heads.iter().enumerate() {
+        let i = head.1 * width + head.0;
+        if distances[i] == 0 {
+            owners[i] = None;
+        } else {
+            distances[i] = 0;
+            owners[i] = Some(player);
+            queue.push_back(i);
+        }
+    }
+
+    while let Some(current) = queue.pop_front() {
+        let pos = (current % width, current / width);
+        let new_distance = distances[current] + 1;
+
+        for direction in Direction::all_directions() {
+            let new_pos = match topology.offset_pos(pos, direction, size) {
+                Some(new_pos) => new_pos,
+                None => continue,
+            };
+            let new_i = new_pos.1 * width + new_pos.0;
+
+            if occupied_mask[new_i] {
+                continue;
+            }
+
+            if distances[new_i] == usize::MAX {
+                distances[new_i] = new_distance;
+                owners[new_i] = owners[current];
+                queue.push_back(new_i);
+            } else if distances[new_i] == new_distance && owners[new_i] != owners[current] {
+                owners[new_i] = None;
+            }
+        }
+    }
+
+    owners
+}
+
+pub fn count_controlled(owners: &[Option<usize>], player: usize) -> usize {
+    owners
+        .iter()
+        .filter(|&&owner| owner == Some(player))
+        .count()
+}